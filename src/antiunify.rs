@@ -0,0 +1,467 @@
+use std::collections::{HashMap, HashSet};
+
+use syn::visit::Visit;
+
+use crate::typ::{RustType, TVar};
+use crate::unify::Substitution;
+
+/// Least general generalisation of two terms, along with the substitutions mapping it back to
+/// each of them
+#[derive(Clone, Debug)]
+pub struct Generalisation<T> {
+    term: T,
+    left: Substitution,
+    right: Substitution,
+    params: Vec<(syn::Ident, syn::Expr, syn::Expr)>,
+}
+
+impl<T> Generalisation<T> {
+    pub fn term(&self) -> &T {
+        &self.term
+    }
+
+    pub fn into_term(self) -> T {
+        self.term
+    }
+
+    /// Types the introduced type variables stand for in the first term
+    pub fn left(&self) -> &Substitution {
+        &self.left
+    }
+
+    /// Types the introduced type variables stand for in the second term
+    pub fn right(&self) -> &Substitution {
+        &self.right
+    }
+
+    /// Parameters introduced for differing sub-expressions, with the expressions they stand for
+    /// in the first and second term
+    pub fn params(&self) -> &Vec<(syn::Ident, syn::Expr, syn::Expr)> {
+        &self.params
+    }
+}
+
+/// Collects every identifier mentioned in a syntax tree
+#[derive(Default)]
+struct Idents(HashSet<String>);
+
+impl<'ast> Visit<'ast> for Idents {
+    fn visit_ident(&mut self, i: &'ast proc_macro2::Ident) {
+        self.0.insert(i.to_string());
+    }
+}
+
+/// Returns the type with its children replaced by `()`, so that two types have the same
+/// outermost constructor iff their heads are equal
+fn head(ty: &RustType) -> RustType {
+    let mut ty = ty.clone();
+    for child in ty.children_mut() {
+        *child = RustType::Unit
+    }
+    ty
+}
+
+/// State of the anti-unification of two terms. Each pair of differing sub-terms is abstracted
+/// by a single variable, however many times it occurs, which makes the result least general.
+#[derive(Default)]
+struct AntiUnifier {
+    /// names that fresh type variables and parameters must avoid
+    used: HashSet<String>,
+    /// identifiers bound by the `let` statements enclosing the sub-terms being generalised
+    locals: Vec<HashSet<String>>,
+    next_var: usize,
+    next_param: usize,
+    types: HashMap<(RustType, RustType), TVar>,
+    params: Vec<(syn::Ident, syn::Expr, syn::Expr)>,
+}
+
+impl AntiUnifier {
+    fn fresh_var(&mut self) -> TVar {
+        loop {
+            let tvar = TVar(self.next_var);
+            self.next_var += 1;
+            if !self.used.contains(&tvar.to_string()) {
+                return tvar;
+            }
+        }
+    }
+
+    fn fresh_param(&mut self) -> syn::Ident {
+        loop {
+            let name = format!("p{}", self.next_param);
+            self.next_param += 1;
+            if !self.used.contains(&name) {
+                return syn::Ident::new(&name, proc_macro2::Span::call_site());
+            }
+        }
+    }
+
+    fn ty(&mut self, t1: &RustType, t2: &RustType) -> RustType {
+        if t1 == t2 {
+            return t1.clone();
+        }
+        if head(t1) == head(t2) {
+            let mut generalised = t1.clone();
+            let children = t1
+                .children()
+                .into_iter()
+                .zip(t2.children())
+                .map(|(c1, c2)| self.ty(c1, c2))
+                .collect::<Vec<_>>();
+            for (slot, child) in generalised.children_mut().into_iter().zip(children) {
+                *slot = child
+            }
+            return generalised;
+        }
+        let key = (t1.clone(), t2.clone());
+        if let Some(tvar) = self.types.get(&key) {
+            return RustType::TVar(*tvar);
+        }
+        let tvar = self.fresh_var();
+        self.types.insert(key, tvar);
+        RustType::TVar(tvar)
+    }
+
+    /// Abstracts a pair of differing expressions by a parameter, unless one of them mentions a
+    /// local binding that would not be in scope of the caller
+    fn param(&mut self, e1: &syn::Expr, e2: &syn::Expr) -> Option<syn::Expr> {
+        let mut idents = Idents::default();
+        idents.visit_expr(e1);
+        idents.visit_expr(e2);
+        if self
+            .locals
+            .iter()
+            .any(|locals| !locals.is_disjoint(&idents.0))
+        {
+            return None;
+        }
+        let existing = self
+            .params
+            .iter()
+            .find(|(_, p1, p2)| p1 == e1 && p2 == e2)
+            .map(|(param, _, _)| param.clone());
+        let param = match existing {
+            Some(param) => param,
+            None => {
+                let param = self.fresh_param();
+                self.params.push((param.clone(), e1.clone(), e2.clone()));
+                param
+            }
+        };
+        Some(syn::Expr::Path(syn::ExprPath {
+            attrs: vec![],
+            qself: None,
+            path: param.into(),
+        }))
+    }
+
+    fn exprs<'a, I>(&mut self, e1: I, e2: I) -> Option<Vec<syn::Expr>>
+    where
+        I: IntoIterator<Item = &'a syn::Expr>,
+    {
+        e1.into_iter()
+            .zip(e2)
+            .map(|(e1, e2)| self.expr(e1, e2))
+            .collect()
+    }
+
+    fn expr(&mut self, e1: &syn::Expr, e2: &syn::Expr) -> Option<syn::Expr> {
+        if e1 == e2 {
+            return Some(e1.clone());
+        }
+        // drop the variables and parameters introduced by a failed attempt
+        let saved = (
+            self.types.clone(),
+            self.params.len(),
+            self.next_var,
+            self.next_param,
+        );
+        let generalised = self.expr_shape(e1, e2);
+        if generalised.is_none() {
+            self.types = saved.0;
+            self.params.truncate(saved.1);
+            (self.next_var, self.next_param) = (saved.2, saved.3);
+        }
+        generalised.or_else(|| self.param(e1, e2))
+    }
+
+    /// Generalises two expressions with the same outermost construct, None if they differ
+    fn expr_shape(&mut self, e1: &syn::Expr, e2: &syn::Expr) -> Option<syn::Expr> {
+        use syn::Expr;
+        let expr = match (e1, e2) {
+            (Expr::Binary(b1), Expr::Binary(b2)) if b1.op == b2.op => {
+                Expr::Binary(syn::ExprBinary {
+                    left: Box::new(self.expr(&b1.left, &b2.left)?),
+                    right: Box::new(self.expr(&b1.right, &b2.right)?),
+                    ..b1.clone()
+                })
+            }
+            (Expr::Unary(u1), Expr::Unary(u2)) if u1.op == u2.op => Expr::Unary(syn::ExprUnary {
+                expr: Box::new(self.expr(&u1.expr, &u2.expr)?),
+                ..u1.clone()
+            }),
+            (Expr::Paren(p1), Expr::Paren(p2)) => Expr::Paren(syn::ExprParen {
+                expr: Box::new(self.expr(&p1.expr, &p2.expr)?),
+                ..p1.clone()
+            }),
+            (Expr::Reference(r1), Expr::Reference(r2)) if r1.mutability == r2.mutability => {
+                Expr::Reference(syn::ExprReference {
+                    expr: Box::new(self.expr(&r1.expr, &r2.expr)?),
+                    ..r1.clone()
+                })
+            }
+            (Expr::Field(f1), Expr::Field(f2)) if f1.member == f2.member => {
+                Expr::Field(syn::ExprField {
+                    base: Box::new(self.expr(&f1.base, &f2.base)?),
+                    ..f1.clone()
+                })
+            }
+            (Expr::Index(i1), Expr::Index(i2)) => Expr::Index(syn::ExprIndex {
+                expr: Box::new(self.expr(&i1.expr, &i2.expr)?),
+                index: Box::new(self.expr(&i1.index, &i2.index)?),
+                ..i1.clone()
+            }),
+            (Expr::Call(c1), Expr::Call(c2)) if c1.args.len() == c2.args.len() => {
+                Expr::Call(syn::ExprCall {
+                    func: Box::new(self.expr(&c1.func, &c2.func)?),
+                    args: self.exprs(&c1.args, &c2.args)?.into_iter().collect(),
+                    ..c1.clone()
+                })
+            }
+            (Expr::MethodCall(m1), Expr::MethodCall(m2))
+                if m1.method == m2.method
+                    && m1.turbofish == m2.turbofish
+                    && m1.args.len() == m2.args.len() =>
+            {
+                Expr::MethodCall(syn::ExprMethodCall {
+                    receiver: Box::new(self.expr(&m1.receiver, &m2.receiver)?),
+                    args: self.exprs(&m1.args, &m2.args)?.into_iter().collect(),
+                    ..m1.clone()
+                })
+            }
+            (Expr::Tuple(t1), Expr::Tuple(t2)) if t1.elems.len() == t2.elems.len() => {
+                Expr::Tuple(syn::ExprTuple {
+                    elems: self.exprs(&t1.elems, &t2.elems)?.into_iter().collect(),
+                    ..t1.clone()
+                })
+            }
+            (Expr::Array(a1), Expr::Array(a2)) if a1.elems.len() == a2.elems.len() => {
+                Expr::Array(syn::ExprArray {
+                    elems: self.exprs(&a1.elems, &a2.elems)?.into_iter().collect(),
+                    ..a1.clone()
+                })
+            }
+            (Expr::Cast(c1), Expr::Cast(c2)) => {
+                let ty = match (RustType::try_from(&*c1.ty), RustType::try_from(&*c2.ty)) {
+                    _ if c1.ty == c2.ty => c1.ty.clone(),
+                    (Ok(t1), Ok(t2)) => Box::new(self.ty(&t1, &t2).into()),
+                    _ => return None,
+                };
+                Expr::Cast(syn::ExprCast {
+                    expr: Box::new(self.expr(&c1.expr, &c2.expr)?),
+                    ty,
+                    ..c1.clone()
+                })
+            }
+            (Expr::Assign(a1), Expr::Assign(a2)) => Expr::Assign(syn::ExprAssign {
+                left: Box::new(self.expr(&a1.left, &a2.left)?),
+                right: Box::new(self.expr(&a1.right, &a2.right)?),
+                ..a1.clone()
+            }),
+            (Expr::AssignOp(a1), Expr::AssignOp(a2)) if a1.op == a2.op => {
+                Expr::AssignOp(syn::ExprAssignOp {
+                    left: Box::new(self.expr(&a1.left, &a2.left)?),
+                    right: Box::new(self.expr(&a1.right, &a2.right)?),
+                    ..a1.clone()
+                })
+            }
+            (Expr::Return(r1), Expr::Return(r2)) => Expr::Return(syn::ExprReturn {
+                expr: match (&r1.expr, &r2.expr) {
+                    (Some(e1), Some(e2)) => Some(Box::new(self.expr(e1, e2)?)),
+                    _ => return None,
+                },
+                ..r1.clone()
+            }),
+            (Expr::Block(b1), Expr::Block(b2)) if b1.label == b2.label => {
+                Expr::Block(syn::ExprBlock {
+                    block: self.block(&b1.block, &b2.block)?,
+                    ..b1.clone()
+                })
+            }
+            (Expr::Unsafe(u1), Expr::Unsafe(u2)) => Expr::Unsafe(syn::ExprUnsafe {
+                block: self.block(&u1.block, &u2.block)?,
+                ..u1.clone()
+            }),
+            (Expr::If(i1), Expr::If(i2)) => Expr::If(syn::ExprIf {
+                cond: Box::new(self.expr(&i1.cond, &i2.cond)?),
+                then_branch: self.block(&i1.then_branch, &i2.then_branch)?,
+                else_branch: match (&i1.else_branch, &i2.else_branch) {
+                    (None, None) => None,
+                    (Some((token, e1)), Some((_, e2))) => {
+                        Some((*token, Box::new(self.expr(e1, e2)?)))
+                    }
+                    _ => return None,
+                },
+                ..i1.clone()
+            }),
+            _ => return None,
+        };
+        Some(expr)
+    }
+
+    /// Generalises two blocks statement by statement. The identifiers bound by `let`
+    /// statements are in scope of the following statements, which keeps them from being
+    /// abstracted away.
+    fn block(&mut self, b1: &syn::Block, b2: &syn::Block) -> Option<syn::Block> {
+        if b1.stmts.len() != b2.stmts.len() {
+            return None;
+        }
+        self.locals.push(HashSet::new());
+        let stmts = b1
+            .stmts
+            .iter()
+            .zip(b2.stmts.iter())
+            .map(|(s1, s2)| self.stmt(s1, s2))
+            .collect::<Option<Vec<_>>>();
+        self.locals.pop();
+        Some(syn::Block {
+            brace_token: b1.brace_token,
+            stmts: stmts?,
+        })
+    }
+
+    fn stmt(&mut self, s1: &syn::Stmt, s2: &syn::Stmt) -> Option<syn::Stmt> {
+        use syn::Stmt;
+        let stmt = match (s1, s2) {
+            (Stmt::Expr(e1), Stmt::Expr(e2)) => Stmt::Expr(self.expr(e1, e2)?),
+            (Stmt::Semi(e1, semi), Stmt::Semi(e2, _)) => Stmt::Semi(self.expr(e1, e2)?, *semi),
+            (Stmt::Local(l1), Stmt::Local(l2)) if l1.pat == l2.pat => {
+                let init = match (&l1.init, &l2.init) {
+                    (None, None) => None,
+                    (Some((eq, i1)), Some((_, i2))) => Some((*eq, Box::new(self.expr(i1, i2)?))),
+                    _ => return None,
+                };
+                let mut bound = Idents::default();
+                bound.visit_pat(&l1.pat);
+                self.locals.last_mut().unwrap().extend(bound.0);
+                Stmt::Local(syn::Local { init, ..l1.clone() })
+            }
+            (s1, s2) if s1 == s2 => s1.clone(),
+            _ => return None,
+        };
+        Some(stmt)
+    }
+
+    fn generalisation<T>(self, term: T) -> Generalisation<T> {
+        let left = self
+            .types
+            .iter()
+            .map(|((t1, _), tvar)| (*tvar, t1.clone()))
+            .collect();
+        let right = self
+            .types
+            .iter()
+            .map(|((_, t2), tvar)| (*tvar, t2.clone()))
+            .collect();
+        Generalisation {
+            term,
+            left,
+            right,
+            params: self.params,
+        }
+    }
+}
+
+/// Computes the least general generalisation of two types, replacing the sub-terms where they
+/// differ by fresh type variables
+pub fn anti_unify(t1: &RustType, t2: &RustType) -> Generalisation<RustType> {
+    let mut anti = AntiUnifier::default();
+    let vars = t1.free_vars().into_iter().chain(t2.free_vars());
+    anti.used = vars.map(|tvar| tvar.to_string()).collect();
+    let ty = anti.ty(t1, t2);
+    anti.generalisation(ty)
+}
+
+/// Computes the least general generalisation of two expressions, replacing the sub-expressions
+/// where they differ by parameters `p0..pn`, and the types of casts where they differ by type
+/// variables. Sub-expressions mentioning a variable bound inside the expressions are never
+/// abstracted, so the parameters can be passed by the caller.
+pub fn anti_unify_exprs(e1: &syn::Expr, e2: &syn::Expr) -> Generalisation<syn::Expr> {
+    let mut idents = Idents::default();
+    idents.visit_expr(e1);
+    idents.visit_expr(e2);
+    let mut anti = AntiUnifier {
+        used: idents.0,
+        ..AntiUnifier::default()
+    };
+    // no local is in scope of the whole expressions, so they can always be abstracted
+    let expr = anti.expr(e1, e2).unwrap();
+    anti.generalisation(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CHRusty_parse;
+    use quote::ToTokens;
+
+    fn ty(src: &str) -> RustType {
+        CHRusty_parse!(src as syn::Type).into()
+    }
+
+    fn expr(src: &str) -> syn::Expr {
+        CHRusty_parse!(src as syn::Expr)
+    }
+
+    #[test]
+    fn test_types_are_generalised_where_they_differ() {
+        let (t1, t2) = (
+            ty("(Vec<i32>, &i32, *mut u8)"),
+            ty("(Vec<f64>, &f64, *const u8)"),
+        );
+        let general = anti_unify(&t1, &t2);
+        assert_eq!(
+            format!("{}", general.term()),
+            "tuple(vec(T0), ref_immutable_T0, T1)"
+        );
+        assert_eq!(general.left().apply(general.term()), t1);
+        assert_eq!(general.right().apply(general.term()), t2);
+
+        // variables of the inputs are not reused
+        let t1 = RustType::Option(Box::new(RustType::TVar(TVar(0))));
+        let general = anti_unify(&t1, &ty("Option<u8>"));
+        assert_eq!(
+            general.term(),
+            &RustType::Option(Box::new(RustType::TVar(TVar(1))))
+        );
+    }
+
+    #[test]
+    fn test_expressions_are_generalised_where_they_differ() {
+        let (e1, e2) = (
+            expr("{ let n = xs.len() as i32; total += n * 2; total }"),
+            expr("{ let n = ys.len() as u64; total += n * 3; total }"),
+        );
+        let general = anti_unify_exprs(&e1, &e2);
+        assert_eq!(
+            general.term().to_token_stream().to_string(),
+            "{ let n = p0 . len () as T0 ; total += n * p1 ; total }"
+        );
+        let params = general
+            .params()
+            .iter()
+            .map(|(p, e1, e2)| {
+                format!("{}: {} / {}", p, e1.to_token_stream(), e2.to_token_stream())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(params, vec!["p0: xs / ys", "p1: 2 / 3"]);
+        assert_eq!(general.right().get(&TVar(0)), Some(&RustType::U64));
+
+        // `n` is bound inside the block, so the differing uses of it cannot be abstracted
+        let general = anti_unify_exprs(
+            &expr("{ let n = 1; f(n + 1) }"),
+            &expr("{ let n = 1; f(n) }"),
+        );
+        assert_eq!(general.term().to_token_stream().to_string(), "p0");
+    }
+}
@@ -0,0 +1,289 @@
+use crate::traits::TraitEnv;
+use crate::typ::{Error, RustMutability, RustReceiver, RustType, RustTypeSignature, TVar};
+use crate::unify::{Substitution, Unifier};
+
+/// Adjustment needed for an argument to be accepted where a parameter of another type is
+/// expected
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Coercion {
+    /// the receiver has to be borrowed at the call site, e.g `v` passed for `&self`
+    AutoRef(RustMutability),
+    /// `&mut T` passed for `&T`
+    MutToShared,
+    /// `&[T; n]` passed for `&[T]`
+    Unsize,
+}
+
+/// Reason a call site does not match the signature of the function it calls
+#[derive(Debug)]
+pub enum CallError {
+    Arity {
+        expected: usize,
+        found: usize,
+    },
+    /// the argument at `index`, counting the receiver, does not fit its parameter
+    Argument {
+        index: usize,
+        expected: Box<RustType>,
+        found: Box<RustType>,
+        error: Error,
+    },
+    /// a generic parameter was instantiated with a type that does not satisfy its bounds, `index`
+    /// is the first argument mentioning the parameter
+    Bound {
+        index: Option<usize>,
+        error: Error,
+    },
+}
+
+impl std::fmt::Display for CallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallError::Arity { expected, found } => {
+                write!(f, "expected {} arguments, found {}", expected, found)
+            }
+            CallError::Argument {
+                index,
+                expected,
+                found,
+                error,
+            } => write!(
+                f,
+                "argument {}: expected {}, found {} ({})",
+                index, expected, found, error
+            ),
+            CallError::Bound {
+                index: Some(index),
+                error,
+            } => write!(f, "argument {}: {}", index, error),
+            CallError::Bound { index: None, error } => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Outcome of a successful call-site check
+#[derive(Clone, Debug)]
+pub struct CallSite {
+    coercions: Vec<Vec<Coercion>>,
+    subst: Substitution,
+    out_ty: RustType,
+}
+
+impl CallSite {
+    /// Coercions applied to each argument, counting the receiver, in order
+    pub fn coercions(&self) -> &Vec<Vec<Coercion>> {
+        &self.coercions
+    }
+
+    /// Bindings of the type variables of the instantiated signature and of the arguments
+    pub fn substitution(&self) -> &Substitution {
+        &self.subst
+    }
+
+    /// Type of the call expression
+    pub fn out_ty(&self) -> &RustType {
+        &self.out_ty
+    }
+}
+
+/// Unifies `found` with `expected`, falling back on the coercions applied by rustc to
+/// references when the types do not match as they are. Only receivers are borrowed
+/// automatically (`auto_ref`), the caller is expected to insert the borrows reported as
+/// `Coercion::AutoRef`.
+fn coerce(
+    unifier: &mut Unifier,
+    expected: &RustType,
+    found: &RustType,
+    auto_ref: bool,
+) -> Result<Vec<Coercion>, Error> {
+    let err = match unifier.unify(expected, found) {
+        Ok(_) => return Ok(vec![]),
+        Err(err) => err,
+    };
+    let RustType::Reference(_, mutability, expected) = unifier.substitution().apply(expected)
+    else {
+        return Err(err);
+    };
+    let (mut coercions, found) = match found {
+        RustType::Reference(_, m, found) if *m == mutability => (vec![], &**found),
+        RustType::Reference(_, RustMutability::Mutable, found) => {
+            (vec![Coercion::MutToShared], &**found)
+        }
+        RustType::Reference(..) => return Err(err),
+        found if auto_ref => (vec![Coercion::AutoRef(mutability)], found),
+        _ => return Err(err),
+    };
+    if unifier.unify(&expected, found).is_ok() {
+        return Ok(coercions);
+    }
+    match (&*expected, unifier.substitution().apply(found)) {
+        (RustType::Slice(elt), RustType::Array(found, _)) => {
+            unifier.unify(elt, &found).map_err(|_| err)?;
+            coercions.push(Coercion::Unsize);
+            Ok(coercions)
+        }
+        _ => Err(err),
+    }
+}
+
+/// Checks that a call passing arguments of types `args` to a function of signature `sig` would
+/// type-check, instantiating the generic parameters of the signature and checking their bounds
+/// in `env`. The receiver of a method is passed as the first argument.
+pub fn check_call(
+    sig: &RustTypeSignature,
+    args: &[RustType],
+    env: &TraitEnv,
+) -> Result<CallSite, CallError> {
+    // fresh variables must not capture the variables of the signature nor of the arguments
+    let mut next = sig
+        .free_vars()
+        .into_iter()
+        .chain(sig.constraints().iter().map(|(tvar, _)| *tvar))
+        .chain(args.iter().flat_map(|arg| arg.free_vars()))
+        .map(|tvar| tvar.0 + 1)
+        .max()
+        .unwrap_or(0);
+    let mut fresh = || {
+        next += 1;
+        TVar(next - 1)
+    };
+    let (sig, _) = sig.instantiate(|| RustType::TVar(fresh()));
+
+    let self_var = fresh();
+    let self_ty = RustType::TVar(self_var);
+    let receiver = sig.receiver().map(|receiver| match receiver {
+        RustReceiver::Value(_) => self_ty.clone(),
+        RustReceiver::Reference(lt, m) => {
+            RustType::Reference(lt.clone(), m.clone(), Box::new(self_ty.clone()))
+        }
+        RustReceiver::Typed(_, ty) => {
+            let self_alias =
+                RustType::CAlias(syn::Ident::new("Self", proc_macro2::Span::call_site()));
+            ty.abstract_over(&self_alias, self_var)
+        }
+    });
+    let params = receiver
+        .into_iter()
+        .chain(sig.args().iter().map(|(_, ty)| ty.clone()))
+        .collect::<Vec<_>>();
+    if params.len() != args.len() {
+        return Err(CallError::Arity {
+            expected: params.len(),
+            found: args.len(),
+        });
+    }
+
+    let mut unifier = Unifier::new();
+    let mut coercions = vec![];
+    for (index, (expected, found)) in params.iter().zip(args.iter()).enumerate() {
+        let auto_ref = index == 0 && sig.receiver().is_some();
+        match coerce(&mut unifier, expected, found, auto_ref) {
+            Ok(applied) => coercions.push(applied),
+            Err(error) => {
+                let subst = unifier.substitution();
+                return Err(CallError::Argument {
+                    index,
+                    expected: Box::new(subst.apply(expected)),
+                    found: Box::new(subst.apply(found)),
+                    error,
+                });
+            }
+        }
+    }
+
+    let subst = unifier.into_substitution();
+    for constraint in sig.constraints().iter() {
+        if let Err(error) = env.check(std::slice::from_ref(constraint), &subst) {
+            let index = params
+                .iter()
+                .position(|param| param.free_vars().contains(&constraint.0));
+            return Err(CallError::Bound { index, error });
+        }
+    }
+    let out_ty = sig.out_ty().map_or(RustType::Unit, |ty| subst.apply(ty));
+    Ok(CallSite {
+        coercions,
+        subst,
+        out_ty,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CHRusty_parse;
+
+    fn ty(src: &str) -> RustType {
+        CHRusty_parse!(src as syn::Type).into()
+    }
+
+    fn sig(src: &str) -> RustTypeSignature {
+        CHRusty_parse!(src as syn::Signature).into()
+    }
+
+    #[test]
+    fn test_arguments_are_coerced_and_generics_instantiated() {
+        let env = TraitEnv::builtin();
+        let sum = sig("fn sum<T0: Copy>(xs: &[T0], acc: &mut T0, scale: &T0) -> T0");
+        let call = check_call(
+            &sum,
+            &[ty("&mut [i32; 4]"), ty("&mut i32"), ty("&mut i32")],
+            &env,
+        )
+        .unwrap();
+        assert_eq!(
+            call.coercions(),
+            &vec![
+                vec![Coercion::MutToShared, Coercion::Unsize],
+                vec![],
+                vec![Coercion::MutToShared],
+            ]
+        );
+        assert_eq!(call.out_ty(), &ty("i32"));
+
+        let len = sig("fn len(&self, offset: libc::size_t) -> usize");
+        let call = check_call(&len, &[ty("Vec<u8>"), ty("usize")], &env).unwrap();
+        assert_eq!(
+            call.coercions(),
+            &vec![vec![Coercion::AutoRef(RustMutability::Immutable)], vec![]]
+        );
+        assert_eq!(call.out_ty(), &ty("usize"));
+    }
+
+    #[test]
+    fn test_failing_arguments_are_reported() {
+        let env = TraitEnv::builtin();
+        let swap = sig("fn swap<T0: Copy>(a: &mut T0, b: &mut T0)");
+        match check_call(&swap, &[ty("&mut u8"), ty("&u8")], &env) {
+            Err(CallError::Argument { index: 1, .. }) => (),
+            res => panic!("unexpected result {:?}", res),
+        }
+        // only the receiver is borrowed automatically
+        match check_call(&swap, &[ty("&mut u8"), ty("u8")], &env) {
+            Err(CallError::Argument { index: 1, .. }) => (),
+            res => panic!("unexpected result {:?}", res),
+        }
+        match check_call(&swap, &[ty("&mut u8"), ty("&mut u16")], &env) {
+            Err(CallError::Argument {
+                index: 1, expected, ..
+            }) => assert_eq!(*expected, ty("&mut u8")),
+            res => panic!("unexpected result {:?}", res),
+        }
+        match check_call(&swap, &[ty("&mut Vec<u8>"), ty("&mut Vec<u8>")], &env) {
+            Err(err @ CallError::Bound { index: Some(0), .. }) => {
+                assert_eq!(
+                    format!("{}", err),
+                    "argument 0: vec(u8) does not implement Copy"
+                )
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(matches!(
+            check_call(&swap, &[ty("&mut u8")], &env),
+            Err(CallError::Arity {
+                expected: 2,
+                found: 1
+            })
+        ));
+    }
+}
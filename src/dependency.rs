@@ -0,0 +1,329 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::typ::{ProgramTypeContext, RustFields, RustGenerics, RustPath, RustType};
+
+/// Kind of declaration a node of the dependency graph stands for
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DeclKind {
+    Alias,
+    Struct,
+    Enum,
+    Union,
+}
+
+impl std::fmt::Display for DeclKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeclKind::Alias => write!(f, "type"),
+            DeclKind::Struct => write!(f, "struct"),
+            DeclKind::Enum => write!(f, "enum"),
+            DeclKind::Union => write!(f, "union"),
+        }
+    }
+}
+
+/// Graph of the declarations of a `ProgramTypeContext`, with an edge from each declaration to
+/// every declaration its definition mentions. Types that are not declared in the context, such
+/// as `std` or `libc` types, are left out.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeGraph {
+    nodes: BTreeMap<RustPath, DeclKind>,
+    edges: BTreeMap<RustPath, BTreeSet<RustPath>>,
+}
+
+/// Collects the paths of every nominal type mentioned in `ty`
+fn references(ty: &RustType, acc: &mut BTreeSet<RustPath>) {
+    match ty {
+        RustType::CAlias(id) => {
+            acc.insert(id.clone().into());
+        }
+        RustType::Adt(path, _, _) => {
+            acc.insert(path.clone());
+        }
+        _ => (),
+    }
+    for child in ty.children() {
+        references(child, acc)
+    }
+}
+
+fn generics_references(generics: &RustGenerics, acc: &mut BTreeSet<RustPath>) {
+    for ty in generics
+        .params
+        .iter()
+        .flat_map(|(_, bounds)| bounds.iter().flat_map(|bound| bound.types()))
+    {
+        references(ty, acc)
+    }
+}
+
+fn fields_references(fields: &RustFields, acc: &mut BTreeSet<RustPath>) {
+    for ty in fields.types() {
+        references(ty, acc)
+    }
+}
+
+impl TypeGraph {
+    pub fn new(ctxt: &ProgramTypeContext) -> Self {
+        let mut nodes = BTreeMap::new();
+        let mut refs = BTreeMap::new();
+        for (path, ty) in ctxt.0.iter() {
+            let mut acc = BTreeSet::new();
+            references(ty, &mut acc);
+            nodes.insert(path.clone(), DeclKind::Alias);
+            refs.insert(path.clone(), acc);
+        }
+        for (path, st) in ctxt.1.iter() {
+            let mut acc = BTreeSet::new();
+            generics_references(st.generics(), &mut acc);
+            fields_references(st.fields(), &mut acc);
+            nodes.insert(path.clone(), DeclKind::Struct);
+            refs.insert(path.clone(), acc);
+        }
+        for (path, en) in ctxt.2.iter() {
+            let mut acc = BTreeSet::new();
+            generics_references(en.generics(), &mut acc);
+            for variant in en.variants().iter() {
+                fields_references(&variant.fields, &mut acc);
+            }
+            nodes.insert(path.clone(), DeclKind::Enum);
+            refs.insert(path.clone(), acc);
+        }
+        for (path, un) in ctxt.3.iter() {
+            let mut acc = BTreeSet::new();
+            generics_references(un.generics(), &mut acc);
+            fields_references(un.fields(), &mut acc);
+            nodes.insert(path.clone(), DeclKind::Union);
+            refs.insert(path.clone(), acc);
+        }
+        let edges = refs
+            .into_iter()
+            .map(|(path, acc)| {
+                let deps = acc.into_iter().filter(|dep| nodes.contains_key(dep));
+                (path, deps.collect())
+            })
+            .collect();
+        TypeGraph { nodes, edges }
+    }
+
+    /// Returns the declarations in the graph, along with their kind
+    pub fn nodes(&self) -> &BTreeMap<RustPath, DeclKind> {
+        &self.nodes
+    }
+
+    /// Returns the declarations directly mentioned by the definition of `path`
+    pub fn dependencies(&self, path: &RustPath) -> Option<&BTreeSet<RustPath>> {
+        self.edges.get(path)
+    }
+
+    /// Strongly connected components of the graph, each sorted by path, listed so that every
+    /// component comes after the components it depends on
+    pub fn sccs(&self) -> Vec<Vec<RustPath>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: HashMap::new(),
+            lowlink: HashMap::new(),
+            stack: vec![],
+            on_stack: BTreeSet::new(),
+            sccs: vec![],
+        };
+        for path in self.nodes.keys() {
+            if !tarjan.index.contains_key(path) {
+                tarjan.visit(path)
+            }
+        }
+        tarjan.sccs
+    }
+
+    /// Declarations ordered so that each one comes after the declarations it depends on; mutually
+    /// recursive declarations are kept next to each other
+    pub fn topological_order(&self) -> Vec<RustPath> {
+        self.sccs().into_iter().flatten().collect()
+    }
+
+    /// Returns true if the definition of `path` refers back to itself, directly or not
+    pub fn is_recursive(&self, path: &RustPath) -> bool {
+        self.edges.get(path).is_some_and(|deps| deps.contains(path))
+            || self
+                .sccs()
+                .iter()
+                .any(|scc| scc.len() > 1 && scc.contains(path))
+    }
+
+    /// Returns every declaration needed to define the given types, in topological order
+    pub fn closure<'a>(&self, types: impl IntoIterator<Item = &'a RustType>) -> Vec<RustPath> {
+        let mut roots = BTreeSet::new();
+        for ty in types {
+            references(ty, &mut roots)
+        }
+        let mut needed = BTreeSet::new();
+        let mut worklist = roots
+            .into_iter()
+            .filter(|path| self.nodes.contains_key(path))
+            .collect::<Vec<_>>();
+        while let Some(path) = worklist.pop() {
+            if let Some(deps) = self.edges.get(&path) {
+                worklist.extend(deps.iter().filter(|dep| !needed.contains(*dep)).cloned());
+            }
+            needed.insert(path);
+        }
+        self.topological_order()
+            .into_iter()
+            .filter(|path| needed.contains(path))
+            .collect()
+    }
+
+    /// Renders the graph in the graphviz DOT format, highlighting recursive declarations
+    pub fn to_dot(&self) -> String {
+        let recursive = self
+            .sccs()
+            .into_iter()
+            .filter(|scc| scc.len() > 1 || self.edges[&scc[0]].contains(&scc[0]))
+            .flatten()
+            .collect::<BTreeSet<_>>();
+        let mut dot = String::from("digraph types {\n");
+        for (path, kind) in self.nodes.iter() {
+            let color = if recursive.contains(path) {
+                ", color=red"
+            } else {
+                ""
+            };
+            dot.push_str(&format!(
+                "    \"{}\" [label=\"{} {}\"{}];\n",
+                path, kind, path, color
+            ));
+        }
+        for (path, deps) in self.edges.iter() {
+            for dep in deps.iter() {
+                dot.push_str(&format!("    \"{}\" -> \"{}\";\n", path, dep));
+            }
+        }
+        dot.push('}');
+        dot
+    }
+
+    /// Serializes the graph using the versioned schema of `serialize::to_json`
+    pub fn to_json(&self) -> Result<String, Error> {
+        crate::serialize::to_json(self)
+    }
+}
+
+impl From<&ProgramTypeContext> for TypeGraph {
+    fn from(ctxt: &ProgramTypeContext) -> Self {
+        TypeGraph::new(ctxt)
+    }
+}
+
+/// State of Tarjan's strongly connected components algorithm
+struct Tarjan<'a> {
+    graph: &'a TypeGraph,
+    index: HashMap<&'a RustPath, usize>,
+    lowlink: HashMap<&'a RustPath, usize>,
+    stack: Vec<&'a RustPath>,
+    on_stack: BTreeSet<&'a RustPath>,
+    sccs: Vec<Vec<RustPath>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, path: &'a RustPath) {
+        let index = self.index.len();
+        self.index.insert(path, index);
+        self.lowlink.insert(path, index);
+        self.stack.push(path);
+        self.on_stack.insert(path);
+
+        let graph = self.graph;
+        for dep in graph.edges.get(path).into_iter().flatten() {
+            if !self.index.contains_key(dep) {
+                self.visit(dep);
+                let low = self.lowlink[path].min(self.lowlink[dep]);
+                self.lowlink.insert(path, low);
+            } else if self.on_stack.contains(dep) {
+                let low = self.lowlink[path].min(self.index[dep]);
+                self.lowlink.insert(path, low);
+            }
+        }
+
+        if self.lowlink[path] == self.index[path] {
+            let mut scc = vec![];
+            while let Some(member) = self.stack.pop() {
+                self.on_stack.remove(member);
+                scc.push(member.clone());
+                if member == path {
+                    break;
+                }
+            }
+            scc.sort();
+            self.sccs.push(scc);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typ::CTypeContextCollector;
+
+    fn graph_of(src: &str) -> TypeGraph {
+        let file = syn::parse_file(src).unwrap();
+        let mut collector = CTypeContextCollector::default();
+        syn::visit::Visit::visit_file(&mut collector, &file);
+        TypeGraph::new(&collector.to_type_context())
+    }
+
+    fn path(s: &str) -> RustPath {
+        let segments = s.split("::").map(|v| syn::parse_str(v).unwrap());
+        RustPath(segments.collect())
+    }
+
+    #[test]
+    fn test_sccs_are_topologically_ordered() {
+        let graph = graph_of(
+            "
+            mod tree {
+                pub struct Node { children: *mut Forest, value: super::Value }
+                pub struct Forest { first: *mut Node, len: usize }
+            }
+            type Value = Option<Box<Leaf>>;
+            struct Leaf { tag: u8 }
+            struct List { next: *mut List }
+            ",
+        );
+        let sccs = graph.sccs();
+        let position = |name: &str| sccs.iter().position(|scc| scc.contains(&path(name)));
+        assert!(sccs.contains(&vec![path("tree::Forest"), path("tree::Node")]));
+        assert!(position("Leaf") < position("Value"));
+        assert!(position("Value") < position("tree::Node"));
+
+        assert!(graph.is_recursive(&path("List")));
+        assert!(graph.is_recursive(&path("tree::Node")));
+        assert!(!graph.is_recursive(&path("Value")));
+        assert_eq!(graph.topological_order().len(), 5);
+    }
+
+    #[test]
+    fn test_closure_of_a_signature_and_exports() {
+        let graph = graph_of(
+            "
+            struct Point { x: i32, y: i32 }
+            struct Segment { from: Point, to: Point }
+            struct Unused { p: Point }
+            ",
+        );
+        let sig: crate::typ::RustTypeSignature =
+            syn::parse_str::<syn::Signature>("fn length(s: &Segment) -> f64")
+                .unwrap()
+                .into();
+        let types = sig.args().iter().map(|(_, ty)| ty).chain(sig.out_ty());
+        assert_eq!(graph.closure(types), vec![path("Point"), path("Segment")]);
+
+        let dot = graph.to_dot();
+        assert!(dot.contains("\"Segment\" -> \"Point\";"));
+        assert!(dot.contains("\"Unused\" [label=\"struct Unused\"];"));
+        let json = graph.to_json().unwrap();
+        assert!(json.contains("\"Segment\":[\"Point\"]"));
+    }
+}
@@ -5,6 +5,9 @@ pub enum Error {
     IO(std::io::Error),
     StringFormat(FromUtf8Error),
     TypeError(crate::typ::Error),
+    Json(serde_json::Error),
+    /// a serialized value was written with another schema version
+    SchemaVersion(u32),
     Other(String),
 }
 impl From<Error> for String {
@@ -12,6 +15,8 @@ impl From<Error> for String {
         match val {
             Error::IO(ioe) => format!("IO({:?})", ioe),
             Error::TypeError(e) => format!("TypeError({:?})", e),
+            Error::Json(e) => format!("Json({})", e),
+            Error::SchemaVersion(v) => format!("unsupported schema version {}", v),
             Error::StringFormat(f) => format!("{:?}", f),
             Error::Other(st) => format!("Other Error: {}", st),
         }
@@ -24,6 +29,12 @@ impl From<crate::typ::Error> for Error {
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(v: serde_json::Error) -> Self {
+        Error::Json(v)
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(v: std::io::Error) -> Self {
         Error::IO(v)
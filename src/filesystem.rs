@@ -52,7 +52,7 @@ impl FileSystem for RealFileSystem {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct SymbolicFileSystem(Rc<RefCell<HashMap<String, String>>>);
 
 impl FileSystem for SymbolicFileSystem {
@@ -0,0 +1,753 @@
+use std::collections::HashMap;
+
+use ena::unify::{InPlace, InPlaceUnificationTable, Snapshot};
+use syn::visit::Visit;
+use syn::{BinOp, Expr, ItemFn, Lit, Stmt, UnOp};
+
+use crate::annotation::{Annotated, Annotations};
+use crate::labelling::{ASTKey, Label};
+use crate::typ::{
+    Error, ProgramTypeContext, RustMutability, RustPath, RustType, RustTypeSignature, TVar, TypeMap,
+};
+
+/// Type of a label whose type has not been inferred (yet)
+fn label_var(label: Label) -> RustType {
+    RustType::TVar(TVar(label.to_raw()))
+}
+
+/// Snapshot of the state of an inference, see `TypeInference::snapshot`
+pub struct InferenceSnapshot {
+    table: Snapshot<InPlace<Label>>,
+    errors: usize,
+}
+
+/// Infers types for the labels of an annotated AST using a unification table
+///
+/// Types stored in the table may mention other labels as `RustType::TVar(TVar(label))`, so
+/// structural constraints such as `A1 = &mut A0` can be expressed before `A0` is known.
+pub struct TypeInference<'a> {
+    ctxt: &'a ProgramTypeContext,
+    types: &'a TypeMap,
+    /// module of the function being inferred, which the paths of its calls are relative to
+    module: RustPath,
+    table: InPlaceUnificationTable<Label>,
+    errors: Vec<Error>,
+}
+
+impl<'a> TypeInference<'a> {
+    pub fn new(ctxt: &'a ProgramTypeContext, types: &'a TypeMap) -> Self {
+        TypeInference {
+            ctxt,
+            types,
+            module: RustPath::default(),
+            table: InPlaceUnificationTable::new(),
+            errors: vec![],
+        }
+    }
+
+    /// Sets the module that the functions inferred next are declared in, see
+    /// `TypeMapCollector::set_module`
+    pub fn set_module(&mut self, module: RustPath) {
+        self.module = module;
+    }
+
+    /// Returns the errors encountered while generating and solving constraints
+    pub fn errors(&self) -> &Vec<Error> {
+        &self.errors
+    }
+
+    /// Records the current state of the inference, so that it can be restored with `rollback_to`
+    pub fn snapshot(&mut self) -> InferenceSnapshot {
+        InferenceSnapshot {
+            table: self.table.snapshot(),
+            errors: self.errors.len(),
+        }
+    }
+
+    /// Undoes every unification (and forgets every error) since the snapshot was taken
+    pub fn rollback_to(&mut self, snapshot: InferenceSnapshot) {
+        self.table.rollback_to(snapshot.table);
+        self.errors.truncate(snapshot.errors);
+    }
+
+    /// Keeps every unification since the snapshot was taken
+    pub fn commit(&mut self, snapshot: InferenceSnapshot) {
+        self.table.commit(snapshot.table)
+    }
+
+    /// Makes sure the table has a key for `label`
+    fn ensure(&mut self, label: Label) {
+        while self.table.len() <= label.to_raw() {
+            self.table.new_key(None);
+        }
+    }
+
+    /// Returns a label distinct from every label seen so far
+    pub fn fresh(&mut self) -> Label {
+        self.table.new_key(None)
+    }
+
+    /// Returns the current type of `label`, with every label it mentions replaced by its own
+    /// type, or a type variable for the representative of `label` if nothing is known yet
+    pub fn probe(&mut self, label: Label) -> RustType {
+        self.ensure(label);
+        let root = self.table.find(label);
+        match self.table.probe_value(root) {
+            Some(ty) => self.zonk(&ty),
+            None => label_var(root),
+        }
+    }
+
+    /// Replaces every label in a type by its current type
+    fn zonk(&mut self, ty: &RustType) -> RustType {
+        match ty {
+            RustType::TVar(TVar(v)) => self.probe(Label::of_raw(*v)),
+            ty => {
+                let mut ty = ty.clone();
+                for child in ty.children_mut() {
+                    *child = self.zonk(child);
+                }
+                ty
+            }
+        }
+    }
+
+    /// Unifies two types that may mention labels, returning the unified type
+    pub fn unify_types(&mut self, t1: &RustType, t2: &RustType) -> Result<RustType, Error> {
+        let t1 = self.zonk(t1);
+        let t2 = self.zonk(t2);
+        let (ty, subst) = crate::unify::unify(&t1, &t2)?;
+        for (TVar(v), bound) in subst.iter() {
+            let label = Label::of_raw(*v);
+            self.ensure(label);
+            match bound {
+                RustType::TVar(TVar(w)) => {
+                    let other = Label::of_raw(*w);
+                    self.ensure(other);
+                    self.table.unify_var_var(label, other)?
+                }
+                bound => self.table.unify_var_value(label, Some(bound.clone()))?,
+            }
+        }
+        Ok(self.zonk(&ty))
+    }
+
+    /// Constrains `label` to have type `ty`
+    pub fn unify_label(&mut self, label: Label, ty: &RustType) -> Result<RustType, Error> {
+        self.unify_types(&label_var(label), ty)
+    }
+
+    /// Constrains two labels to have the same type
+    pub fn unify_labels(&mut self, l1: Label, l2: Label) -> Result<RustType, Error> {
+        self.unify_types(&label_var(l1), &label_var(l2))
+    }
+
+    /// Converts a syntactic type, resolving type aliases of the program
+    fn lower(&self, ty: &syn::Type) -> Result<RustType, Error> {
+        let mut ty = RustType::try_from(ty)?;
+        ty.resolve(self.ctxt);
+        Ok(ty)
+    }
+
+    /// Looks up the signature of the function called as `path`, by its qualified name. Names
+    /// that match several signatures, e.g functions of a same module declared in different
+    /// files, are ambiguous and have no signature
+    fn signature(&self, path: &syn::Path) -> Option<&'a RustTypeSignature> {
+        let types: &'a TypeMap = self.types;
+        let path = crate::typ::qualify_fn_path(&path.into(), &self.module, self.ctxt);
+        let name = format!("crate::{}", path);
+        let mut matches = types.iter().filter(|(loc, _)| loc.full_fn_name() == name);
+        match (matches.next(), matches.next()) {
+            (Some((_, sig)), None) => Some(sig),
+            _ => None,
+        }
+    }
+
+    /// Generates and solves the constraints of an annotated function, returning the type of
+    /// every label in the annotations
+    pub fn infer<'b>(&mut self, annotated: &Annotated<'b, &'b ItemFn>) -> HashMap<Label, RustType> {
+        let (annotations, f) = annotated;
+        // labels handed out by `fresh` must not collide with labels of the function that have
+        // not been seen by the table yet
+        if let Some(label) = annotations.values().max() {
+            self.ensure(*label);
+        }
+        let mut generator = ConstraintGenerator {
+            inference: self,
+            annotations,
+            out_ty: RustType::Unit,
+        };
+        generator.visit_item_fn(f);
+        annotations
+            .values()
+            .map(|label| (*label, self.probe(*label)))
+            .collect()
+    }
+}
+
+/// Walks an annotated function, emitting unification constraints between labels
+struct ConstraintGenerator<'a, 'b, 'c> {
+    inference: &'b mut TypeInference<'a>,
+    annotations: &'b Annotations<'c>,
+    out_ty: RustType,
+}
+
+impl<'a, 'b, 'c> ConstraintGenerator<'a, 'b, 'c> {
+    fn label(&self, node: &'c dyn ASTKey) -> Option<Label> {
+        self.annotations.get(&node).copied()
+    }
+
+    fn var(&self, node: &'c dyn ASTKey) -> Option<RustType> {
+        self.label(node).map(label_var)
+    }
+
+    /// Records `t1 = t2`, keeping track of any failure
+    fn constrain(&mut self, t1: &RustType, t2: &RustType) {
+        if let Err(err) = self.inference.unify_types(t1, t2) {
+            self.inference.errors.push(err)
+        }
+    }
+
+    /// Records that two nodes have the same type, if both of them were labelled
+    fn constrain_nodes(&mut self, n1: &'c dyn ASTKey, n2: &'c dyn ASTKey) {
+        if let (Some(t1), Some(t2)) = (self.var(n1), self.var(n2)) {
+            self.constrain(&t1, &t2)
+        }
+    }
+
+    fn lower(&mut self, ty: &syn::Type) -> Option<RustType> {
+        self.inference
+            .lower(ty)
+            .map_err(|err| self.inference.errors.push(err))
+            .ok()
+    }
+
+    /// Returns the type a block evaluates to, or None if it diverges
+    fn block_type(&self, block: &'c syn::Block) -> Option<RustType> {
+        match block.stmts.last() {
+            Some(Stmt::Expr(e)) => self.var(e),
+            Some(Stmt::Semi(Expr::Return(_) | Expr::Break(_) | Expr::Continue(_), _)) => None,
+            _ => Some(RustType::Unit),
+        }
+    }
+
+    /// Current type of an expression, looking through references and pointers
+    fn place_type(&mut self, e: &'c Expr) -> Option<RustType> {
+        let mut ty = self.label(e).map(|label| self.inference.probe(label))?;
+        while let RustType::Reference(_, _, box inner) | RustType::Pointer(_, box inner) = ty {
+            ty = inner
+        }
+        Some(ty)
+    }
+
+    /// Instantiates the signature of a callee with fresh labels for its generic parameters
+    fn instantiate(&mut self, sig: &RustTypeSignature) -> (Vec<RustType>, RustType) {
+        let (sig, _) = sig.instantiate(|| label_var(self.inference.fresh()));
+        let args = sig.args().iter().map(|(_, ty)| ty.clone()).collect();
+        let out = sig.out_ty().cloned().unwrap_or(RustType::Unit);
+        (args, out)
+    }
+
+    fn lit_type(lit: &Lit) -> Option<RustType> {
+        match lit {
+            Lit::Str(_) => Some(RustType::Reference(
+                None,
+                RustMutability::Immutable,
+                Box::new(RustType::Str),
+            )),
+            Lit::ByteStr(s) => Some(RustType::Reference(
+                None,
+                RustMutability::Immutable,
+                Box::new(RustType::Array(Box::new(RustType::U8), s.value().len())),
+            )),
+            Lit::Byte(_) => Some(RustType::U8),
+            Lit::Char(_) => Some(RustType::Char),
+            Lit::Bool(_) => Some(RustType::Bool),
+            // unsuffixed numeric literals are left to be determined by their uses
+            Lit::Int(i) if !i.suffix().is_empty() => syn::parse_str::<syn::Type>(i.suffix())
+                .ok()
+                .and_then(|ty| RustType::try_from(&ty).ok()),
+            Lit::Float(f) if !f.suffix().is_empty() => syn::parse_str::<syn::Type>(f.suffix())
+                .ok()
+                .and_then(|ty| RustType::try_from(&ty).ok()),
+            _ => None,
+        }
+    }
+
+    /// Emits the constraints of a single expression, once its children have been visited
+    fn constrain_expr(&mut self, label: Label, e: &'c Expr) {
+        let ty = label_var(label);
+        match e {
+            Expr::Lit(lit) => {
+                if let Some(lit_ty) = Self::lit_type(&lit.lit) {
+                    self.constrain(&ty, &lit_ty)
+                }
+            }
+            Expr::Paren(syn::ExprParen { expr, .. })
+            | Expr::Group(syn::ExprGroup { expr, .. })
+            | Expr::Unary(syn::ExprUnary {
+                op: UnOp::Not(_) | UnOp::Neg(_),
+                expr,
+                ..
+            }) => self.constrain_nodes(e, &**expr),
+            Expr::Unary(syn::ExprUnary {
+                op: UnOp::Deref(_),
+                expr,
+                ..
+            }) => {
+                // the mutability of the operand is unknown, so only resolved operands are used
+                let inner = self.label(&**expr).map(|label| self.inference.probe(label));
+                if let Some(
+                    RustType::Reference(_, _, box inner) | RustType::Pointer(_, box inner),
+                ) = inner
+                {
+                    self.constrain(&ty, &inner)
+                }
+            }
+            Expr::Reference(syn::ExprReference {
+                mutability, expr, ..
+            }) => {
+                if let Some(inner) = self.var(&**expr) {
+                    let mutability = match mutability {
+                        Some(_) => RustMutability::Mutable,
+                        None => RustMutability::Immutable,
+                    };
+                    self.constrain(&ty, &RustType::Reference(None, mutability, Box::new(inner)))
+                }
+            }
+            Expr::Binary(syn::ExprBinary {
+                left, op, right, ..
+            }) => match op {
+                // operator overloading is not modelled: operands and result share a type
+                BinOp::Add(_)
+                | BinOp::Sub(_)
+                | BinOp::Mul(_)
+                | BinOp::Div(_)
+                | BinOp::Rem(_)
+                | BinOp::BitXor(_)
+                | BinOp::BitAnd(_)
+                | BinOp::BitOr(_) => {
+                    self.constrain_nodes(&**left, &**right);
+                    self.constrain_nodes(e, &**left);
+                }
+                BinOp::Shl(_) | BinOp::Shr(_) => self.constrain_nodes(e, &**left),
+                BinOp::Eq(_)
+                | BinOp::Lt(_)
+                | BinOp::Le(_)
+                | BinOp::Ne(_)
+                | BinOp::Ge(_)
+                | BinOp::Gt(_) => {
+                    self.constrain_nodes(&**left, &**right);
+                    self.constrain(&ty, &RustType::Bool);
+                }
+                BinOp::And(_) | BinOp::Or(_) => {
+                    for operand in [&**left, &**right] {
+                        if let Some(operand) = self.var(operand) {
+                            self.constrain(&operand, &RustType::Bool)
+                        }
+                    }
+                    self.constrain(&ty, &RustType::Bool);
+                }
+                _ => {
+                    self.constrain_nodes(&**left, &**right);
+                    self.constrain(&ty, &RustType::Unit);
+                }
+            },
+            Expr::AssignOp(syn::ExprAssignOp { left, right, .. })
+            | Expr::Assign(syn::ExprAssign { left, right, .. }) => {
+                self.constrain_nodes(&**left, &**right);
+                self.constrain(&ty, &RustType::Unit);
+            }
+            Expr::Tuple(syn::ExprTuple { elems, .. }) => {
+                let elts = elems
+                    .iter()
+                    .map(|elt| {
+                        self.var(elt)
+                            .unwrap_or_else(|| label_var(self.inference.fresh()))
+                    })
+                    .collect::<Vec<_>>();
+                let tuple = if elts.is_empty() {
+                    RustType::Unit
+                } else {
+                    RustType::Tuple(elts)
+                };
+                self.constrain(&ty, &tuple)
+            }
+            Expr::Array(syn::ExprArray { elems, .. }) => {
+                let elt = label_var(self.inference.fresh());
+                for e in elems.iter() {
+                    if let Some(e) = self.var(e) {
+                        self.constrain(&elt, &e)
+                    }
+                }
+                self.constrain(&ty, &RustType::Array(Box::new(elt), elems.len()))
+            }
+            Expr::Repeat(syn::ExprRepeat {
+                expr,
+                len:
+                    box Expr::Lit(syn::ExprLit {
+                        lit: Lit::Int(len), ..
+                    }),
+                ..
+            }) => {
+                if let (Some(elt), Ok(len)) = (self.var(&**expr), len.base10_parse()) {
+                    self.constrain(&ty, &RustType::Array(Box::new(elt), len))
+                }
+            }
+            Expr::Index(syn::ExprIndex { expr, index, .. }) => {
+                // ranges such as `v[1..3]` give a slice of the indexed sequence
+                let (indices, range) = match &**index {
+                    Expr::Range(syn::ExprRange { from, to, .. }) => {
+                        let bounds = from.iter().chain(to.iter()).map(|e| &**e).collect();
+                        (bounds, true)
+                    }
+                    index => (vec![index], false),
+                };
+                let res = match self.place_type(expr) {
+                    Some(
+                        RustType::Array(box elt, _)
+                        | RustType::Slice(box elt)
+                        | RustType::Vec(box elt),
+                    ) if range => Some(RustType::Slice(Box::new(elt))),
+                    Some(
+                        RustType::Array(box elt, _)
+                        | RustType::Slice(box elt)
+                        | RustType::Vec(box elt),
+                    ) => Some(elt),
+                    Some(RustType::Str) if range => Some(RustType::Str),
+                    _ => None,
+                };
+                if let Some(res) = res {
+                    self.constrain(&ty, &res);
+                    for index in indices {
+                        if let Some(index) = self.var(index) {
+                            self.constrain(&index, &RustType::Usize)
+                        }
+                    }
+                }
+            }
+            Expr::Field(syn::ExprField { base, member, .. }) => {
+                let structs = &self.inference.ctxt.1;
+                let field = match (self.place_type(base), member) {
+                    (Some(RustType::Tuple(elts)), syn::Member::Unnamed(idx)) => {
+                        elts.get(idx.index as usize).cloned()
+                    }
+                    (Some(RustType::CAlias(name)), member) => structs
+                        .get(&name.into())
+                        .and_then(|st| st.field_type(member, &[])),
+                    (Some(RustType::Adt(path, _, args)), member) => structs
+                        .get(&path)
+                        .and_then(|st| st.field_type(member, &args)),
+                    _ => None,
+                };
+                if let Some(field) = field {
+                    self.constrain(&ty, &field)
+                }
+            }
+            Expr::Call(syn::ExprCall { func, args, .. }) => {
+                let callee = match &**func {
+                    Expr::Path(syn::ExprPath {
+                        path, qself: None, ..
+                    }) => self
+                        .inference
+                        .signature(path)
+                        .map(|sig| self.instantiate(sig)),
+                    _ => None,
+                };
+                let callee = callee.or_else(|| match self.place_type(func) {
+                    Some(RustType::FnPtr {
+                        args, box output, ..
+                    }) => Some((args, output)),
+                    _ => None,
+                });
+                if let Some((params, out)) = callee {
+                    if params.len() != args.len() {
+                        log::warn!(
+                            "call to {} with the wrong number of arguments",
+                            crate::pprint_ast!(&**func)
+                        );
+                    } else {
+                        for (param, arg) in params.iter().zip(args.iter()) {
+                            if let Some(arg) = self.var(arg) {
+                                self.constrain(&arg, param)
+                            }
+                        }
+                    }
+                    self.constrain(&ty, &out)
+                }
+            }
+            Expr::Cast(syn::ExprCast { ty: cast, .. }) => {
+                if let Some(cast) = self.lower(cast) {
+                    self.constrain(&ty, &cast)
+                }
+            }
+            Expr::Type(syn::ExprType {
+                expr, ty: ascribed, ..
+            }) => {
+                if let Some(ascribed) = self.lower(ascribed) {
+                    self.constrain(&ty, &ascribed);
+                    if let Some(expr) = self.var(&**expr) {
+                        self.constrain(&expr, &ascribed)
+                    }
+                }
+            }
+            Expr::Block(syn::ExprBlock { block, .. })
+            | Expr::Unsafe(syn::ExprUnsafe { block, .. }) => {
+                if let Some(block) = self.block_type(block) {
+                    self.constrain(&ty, &block)
+                }
+            }
+            Expr::If(syn::ExprIf {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            }) => {
+                // `if let` conditions are patterns, not booleans
+                if !matches!(&**cond, Expr::Let(_)) {
+                    if let Some(cond) = self.var(&**cond) {
+                        self.constrain(&cond, &RustType::Bool)
+                    }
+                }
+                match else_branch {
+                    Some((_, else_branch)) => {
+                        if let Some(then_ty) = self.block_type(then_branch) {
+                            self.constrain(&ty, &then_ty)
+                        }
+                        if let Some(else_ty) = self.var(&**else_branch) {
+                            self.constrain(&ty, &else_ty)
+                        }
+                    }
+                    None => self.constrain(&ty, &RustType::Unit),
+                }
+            }
+            Expr::While(syn::ExprWhile { cond, .. }) => {
+                if !matches!(&**cond, Expr::Let(_)) {
+                    if let Some(cond) = self.var(&**cond) {
+                        self.constrain(&cond, &RustType::Bool)
+                    }
+                }
+                self.constrain(&ty, &RustType::Unit)
+            }
+            Expr::ForLoop(_) => self.constrain(&ty, &RustType::Unit),
+            Expr::Match(syn::ExprMatch { arms, .. }) => {
+                for arm in arms.iter() {
+                    if let Some((_, guard)) = &arm.guard {
+                        if let Some(guard) = self.var(&**guard) {
+                            self.constrain(&guard, &RustType::Bool)
+                        }
+                    }
+                    if let Some(body) = self.var(&*arm.body) {
+                        self.constrain(&ty, &body)
+                    }
+                }
+            }
+            Expr::Return(syn::ExprReturn { expr, .. }) => {
+                let out_ty = self.out_ty.clone();
+                match expr.as_ref().and_then(|expr| self.var(&**expr)) {
+                    Some(expr) => self.constrain(&expr, &out_ty),
+                    None if expr.is_none() => self.constrain(&RustType::Unit, &out_ty),
+                    None => (),
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl<'a, 'b, 'c> Visit<'c> for ConstraintGenerator<'a, 'b, 'c> {
+    fn visit_item_fn(&mut self, f: &'c ItemFn) {
+        for arg in f.sig.inputs.iter() {
+            if let syn::FnArg::Typed(syn::PatType {
+                pat: box syn::Pat::Ident(syn::PatIdent { ident, .. }),
+                ty,
+                ..
+            }) = arg
+            {
+                if let (Some(label), Some(ty)) = (self.label(ident), self.lower(ty)) {
+                    self.constrain(&label_var(label), &ty)
+                }
+            }
+        }
+        self.out_ty = match &f.sig.output {
+            syn::ReturnType::Default => RustType::Unit,
+            syn::ReturnType::Type(_, ty) => self
+                .lower(ty)
+                .unwrap_or_else(|| label_var(self.inference.fresh())),
+        };
+        self.visit_block(&f.block);
+        if let Some(body) = self.block_type(&f.block) {
+            let out_ty = self.out_ty.clone();
+            self.constrain(&body, &out_ty)
+        }
+    }
+
+    fn visit_local(&mut self, i: &'c syn::Local) {
+        syn::visit::visit_local(self, i);
+        let Some(label) = self.label(&i.pat) else {
+            return;
+        };
+        if let syn::Pat::Type(syn::PatType { ty, .. }) = &i.pat {
+            if let Some(ty) = self.lower(ty) {
+                self.constrain(&label_var(label), &ty)
+            }
+        }
+        if let Some(init) = i.init.as_ref().and_then(|(_, init)| self.var(&**init)) {
+            self.constrain(&label_var(label), &init)
+        }
+    }
+
+    fn visit_expr(&mut self, e: &'c Expr) {
+        syn::visit::visit_expr(self, e);
+        if let Some(label) = self.label(e) {
+            self.constrain_expr(label, e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotation::annotate_ast;
+    use crate::CHRusty_parse;
+
+    fn ty(src: &str) -> RustType {
+        CHRusty_parse!(src as syn::Type).into()
+    }
+
+    #[test]
+    fn test_infer_propagates_through_bindings() {
+        let f = CHRusty_parse!(
+            "fn f(x: &mut Vec<u8>, n: usize) -> bool { let y = &x; let z = (n, 'c'); let w = z.0 + n; w == n }"
+            as ItemFn
+        );
+        let annotated = annotate_ast(&f);
+        let ctxt = Default::default();
+        let types = Default::default();
+        let mut inference = TypeInference::new(&ctxt, &types);
+        let types = inference.infer(&annotated);
+        let by_name = |name: &str| {
+            let ident = syn::Ident::new(name, proc_macro2::Span::call_site());
+            types[&annotated.0[&(&ident as &dyn ASTKey)]].clone()
+        };
+        assert!(inference.errors().is_empty(), "{:?}", inference.errors());
+        assert_eq!(by_name("y"), ty("&&mut Vec<u8>"));
+        assert_eq!(by_name("z"), ty("(usize, char)"));
+        assert_eq!(by_name("w"), ty("usize"));
+    }
+
+    #[test]
+    fn test_ranges_index_slices() {
+        let f = CHRusty_parse!(
+            "fn f(v: Vec<u8>, t: &str, n: usize) { let s = &v[1..n]; let u = &t[..2]; let e = v[0]; }"
+            as ItemFn
+        );
+        let annotated = annotate_ast(&f);
+        let ctxt = Default::default();
+        let types = Default::default();
+        let mut inference = TypeInference::new(&ctxt, &types);
+        let types = inference.infer(&annotated);
+        let by_name = |name: &str| {
+            let ident = syn::Ident::new(name, proc_macro2::Span::call_site());
+            types[&annotated.0[&(&ident as &dyn ASTKey)]].clone()
+        };
+        assert!(inference.errors().is_empty(), "{:?}", inference.errors());
+        assert_eq!(by_name("s"), ty("&[u8]"));
+        assert_eq!(by_name("u"), ty("&str"));
+        assert_eq!(by_name("e"), ty("u8"));
+    }
+
+    #[test]
+    fn test_infer_reports_conflicts() {
+        let f = CHRusty_parse!("fn f(x: u32) -> bool { x }" as ItemFn);
+        let annotated = annotate_ast(&f);
+        let ctxt = Default::default();
+        let types = Default::default();
+        let mut inference = TypeInference::new(&ctxt, &types);
+        inference.infer(&annotated);
+        assert_eq!(inference.errors().len(), 1);
+    }
+
+    #[test]
+    fn test_fresh_labels_do_not_capture_unseen_labels() {
+        let f = CHRusty_parse!(
+            "fn f() { let a = [1u8, 2u8]; let b = true; let c = 'x'; let d = 3i64; }" as ItemFn
+        );
+        let annotated = annotate_ast(&f);
+        let ctxt = Default::default();
+        let types = Default::default();
+        let mut inference = TypeInference::new(&ctxt, &types);
+        let types = inference.infer(&annotated);
+        let by_name = |name: &str| {
+            let ident = syn::Ident::new(name, proc_macro2::Span::call_site());
+            types[&annotated.0[&(&ident as &dyn ASTKey)]].clone()
+        };
+        assert!(inference.errors().is_empty(), "{:?}", inference.errors());
+        assert_eq!(by_name("a"), ty("[u8; 2]"));
+        assert_eq!(by_name("b"), ty("bool"));
+        assert_eq!(by_name("c"), ty("char"));
+        assert_eq!(by_name("d"), ty("i64"));
+    }
+
+    #[test]
+    fn test_calls_are_resolved_by_qualified_name() {
+        let file = syn::parse_file(
+            "
+            pub struct A;
+            pub struct B;
+            pub struct C<T>(T);
+            impl A { fn new() -> u8 { 0 } }
+            impl B { fn new() -> bool { true } }
+            ",
+        )
+        .unwrap();
+        let mut declarations = crate::typ::CTypeContextCollector::default();
+        syn::visit::Visit::visit_file(&mut declarations, &file);
+        let ctxt = declarations.to_type_context();
+        let mut collector = crate::typ::TypeMapCollector::default();
+        collector.set_type_context(ctxt.clone());
+        syn::visit::Visit::visit_file(&mut collector, &file);
+        let mut types = collector.to_type_map();
+        // a same name declared in two files, e.g through `#[path]` attributes
+        for (file, sig) in [
+            ("src/c8.rs", "fn get() -> u8"),
+            ("src/c16.rs", "fn get() -> u16"),
+        ] {
+            let loc = crate::location::RawLoc::new(file.into(), vec![1]);
+            let sig = CHRusty_parse!(sig as syn::Signature).into();
+            types.insert((loc, "crate::C::get".to_string()).into(), sig);
+        }
+
+        let f = CHRusty_parse!(
+            "fn f() { let a = A::new(); let b = B::new(); let c = C::get(); }" as ItemFn
+        );
+        let annotated = annotate_ast(&f);
+        let mut inference = TypeInference::new(&ctxt, &types);
+        let inferred = inference.infer(&annotated);
+        let by_name = |name: &str| {
+            let ident = syn::Ident::new(name, proc_macro2::Span::call_site());
+            inferred[&annotated.0[&(&ident as &dyn ASTKey)]].clone()
+        };
+        assert!(inference.errors().is_empty(), "{:?}", inference.errors());
+        assert_eq!(by_name("a"), ty("u8"));
+        assert_eq!(by_name("b"), ty("bool"));
+        assert!(matches!(by_name("c"), RustType::TVar(_)));
+    }
+
+    #[test]
+    fn test_rollback_undoes_unifications() {
+        let ctxt = Default::default();
+        let types = Default::default();
+        let mut inference = TypeInference::new(&ctxt, &types);
+        let (l0, l1) = (Label::of_raw(0), Label::of_raw(1));
+        inference.unify_label(l0, &ty("Option<u8>")).unwrap();
+
+        let snapshot = inference.snapshot();
+        inference.unify_labels(l0, l1).unwrap();
+        assert_eq!(inference.probe(l1), ty("Option<u8>"));
+        inference.rollback_to(snapshot);
+
+        assert_eq!(inference.probe(l0), ty("Option<u8>"));
+        assert_eq!(inference.probe(l1), label_var(l1));
+    }
+}
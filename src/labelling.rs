@@ -153,6 +153,6 @@ impl UnifyKey for Label {
     }
 
     fn tag() -> &'static str {
-        todo!()
+        "Label"
     }
 }
@@ -0,0 +1,418 @@
+use std::collections::HashSet;
+
+use crate::typ::{
+    CFloatSize, CIntegralSize, Error, ProgramTypeContext, RustAttributes, RustEnum, RustFields,
+    RustPath, RustStruct, RustType, RustUnion,
+};
+
+/// C data model, fixing the sizes of the C integer types and of pointers
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum DataModel {
+    /// 64-bit unix: 32-bit int, 64-bit long and pointers
+    LP64,
+    /// 32-bit platforms: 32-bit int, long and pointers
+    ILP32,
+    /// 64-bit windows: 32-bit int and long, 64-bit pointers
+    LLP64,
+}
+
+impl DataModel {
+    pub fn pointer_size(&self) -> u64 {
+        match self {
+            DataModel::LP64 | DataModel::LLP64 => 8,
+            DataModel::ILP32 => 4,
+        }
+    }
+
+    pub fn int_size(&self, size: &CIntegralSize) -> u64 {
+        match (size, self) {
+            (CIntegralSize::Char, _) => 1,
+            (CIntegralSize::Short, _) => 2,
+            (CIntegralSize::Int, _) => 4,
+            (CIntegralSize::Long, DataModel::LP64) => 8,
+            (CIntegralSize::Long, DataModel::ILP32 | DataModel::LLP64) => 4,
+            (CIntegralSize::LongLong, _) => 8,
+        }
+    }
+
+    pub fn float_size(&self, size: &CFloatSize) -> u64 {
+        match size {
+            CFloatSize::Float => 4,
+            CFloatSize::Double => 8,
+        }
+    }
+}
+
+/// Size and alignment of a type, in bytes
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Layout {
+    pub size: u64,
+    pub align: u64,
+}
+
+impl Layout {
+    /// Layout of a scalar, aligned to its own size
+    fn scalar(size: u64) -> Self {
+        Layout { size, align: size }
+    }
+}
+
+/// Layout of a struct or union, along with the position of each of its fields
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct StructLayout {
+    pub size: u64,
+    pub align: u64,
+    /// offset of each field, in declaration order
+    pub offsets: Vec<u64>,
+    /// padding bytes following each field, up to the next field or the end of the struct
+    pub padding: Vec<u64>,
+}
+
+impl From<&StructLayout> for Layout {
+    fn from(layout: &StructLayout) -> Self {
+        Layout {
+            size: layout.size,
+            align: layout.align,
+        }
+    }
+}
+
+fn round_up(offset: u64, align: u64) -> u64 {
+    offset.div_ceil(align) * align
+}
+
+/// The parts of `#[repr(..)]` that influence layout
+#[derive(Default)]
+struct Repr {
+    c: bool,
+    transparent: bool,
+    packed: Option<u64>,
+    align: Option<u64>,
+    int: Option<RustType>,
+}
+
+impl From<&RustAttributes> for Repr {
+    fn from(attrs: &RustAttributes) -> Self {
+        let mut repr = Repr::default();
+        for arg in attrs.repr.iter() {
+            let value = |prefix: &str| {
+                arg.strip_prefix(prefix)?
+                    .strip_prefix('(')?
+                    .strip_suffix(')')?
+                    .parse()
+                    .ok()
+            };
+            match arg.as_str() {
+                "C" => repr.c = true,
+                "transparent" => repr.transparent = true,
+                "packed" => repr.packed = Some(1),
+                arg if arg.starts_with("packed") => repr.packed = value("packed"),
+                arg if arg.starts_with("align") => repr.align = value("align"),
+                arg => {
+                    repr.int = syn::parse_str::<syn::Type>(arg)
+                        .ok()
+                        .and_then(|ty| RustType::try_from(&ty).ok())
+                        .filter(RustType::is_primitive)
+                }
+            }
+        }
+        repr
+    }
+}
+
+fn no_layout(ty: &RustType, reason: &'static str) -> Error {
+    Error::NoLayout {
+        ty: Box::new(ty.clone()),
+        reason,
+    }
+}
+
+/// Computes layouts, keeping track of the declarations being visited to reject infinite types
+struct LayoutCx<'a> {
+    model: DataModel,
+    ctxt: &'a ProgramTypeContext,
+    visiting: HashSet<RustPath>,
+}
+
+impl<'a> LayoutCx<'a> {
+    fn type_layout(&mut self, ty: &RustType) -> Result<Layout, Error> {
+        let pointer = Layout::scalar(self.model.pointer_size());
+        match ty {
+            RustType::Unit | RustType::Never => Ok(Layout { size: 0, align: 1 }),
+            RustType::Bool | RustType::I8 | RustType::U8 | RustType::CVoid => Ok(Layout::scalar(1)),
+            RustType::I16 | RustType::U16 => Ok(Layout::scalar(2)),
+            RustType::I32 | RustType::U32 | RustType::F32 | RustType::Char => Ok(Layout::scalar(4)),
+            RustType::I64 | RustType::U64 | RustType::F64 => Ok(Layout::scalar(8)),
+            RustType::I128 | RustType::U128 => Ok(Layout::scalar(16)),
+            RustType::Isize | RustType::Usize | RustType::SizeT => Ok(pointer),
+            RustType::CInt { size, .. } => Ok(Layout::scalar(self.model.int_size(size))),
+            RustType::CFloat(size) => Ok(Layout::scalar(self.model.float_size(size))),
+            RustType::FnPtr { .. } => Ok(pointer),
+            RustType::Pointer(_, inner) | RustType::Reference(_, _, inner) => match &**inner {
+                // pointers to unsized types carry a length or a vtable
+                RustType::Str | RustType::Slice(_) | RustType::TraitObject(_) => Ok(Layout {
+                    size: 2 * pointer.size,
+                    align: pointer.align,
+                }),
+                _ => Ok(pointer),
+            },
+            RustType::Array(elt, len) => {
+                let elt = self.type_layout(elt)?;
+                Ok(Layout {
+                    size: elt.size * (*len as u64),
+                    align: elt.align,
+                })
+            }
+            // only the null pointer optimisation is guaranteed
+            RustType::Option(inner) => match &**inner {
+                RustType::Reference(..) | RustType::FnPtr { .. } => self.type_layout(inner),
+                _ => Err(no_layout(ty, "option of a type without a niche")),
+            },
+            RustType::Str | RustType::Slice(_) | RustType::TraitObject(_) => {
+                Err(no_layout(ty, "unsized type"))
+            }
+            RustType::TVar(_) | RustType::Opaque(_) => Err(no_layout(ty, "generic type")),
+            RustType::Tuple(_) | RustType::Vec(_) => {
+                Err(no_layout(ty, "type with an unspecified layout"))
+            }
+            RustType::CAlias(id) => self.nominal_layout(ty, &id.clone().into(), &[]),
+            RustType::Adt(path, _, args) => self.nominal_layout(ty, path, args),
+        }
+    }
+
+    fn nominal_layout(
+        &mut self,
+        ty: &RustType,
+        path: &RustPath,
+        args: &[RustType],
+    ) -> Result<Layout, Error> {
+        if !self.visiting.insert(path.clone()) {
+            return Err(no_layout(ty, "infinitely sized type"));
+        }
+        let ctxt = self.ctxt;
+        let res = if let Some(alias) = ctxt.0.get(path) {
+            self.type_layout(alias)
+        } else if let Some(st) = ctxt.1.get(path) {
+            self.struct_layout(st, args).map(|layout| (&layout).into())
+        } else if let Some(en) = ctxt.2.get(path) {
+            self.enum_layout(ty, en)
+        } else if let Some(un) = ctxt.3.get(path) {
+            self.union_layout(un, args).map(|layout| (&layout).into())
+        } else {
+            Err(no_layout(ty, "type without a declaration"))
+        };
+        self.visiting.remove(path);
+        res
+    }
+
+    /// Layouts of the fields of a declaration, instantiated by `subst`
+    fn field_layouts(
+        &mut self,
+        fields: &RustFields,
+        subst: crate::unify::Substitution,
+    ) -> Result<Vec<Layout>, Error> {
+        fields
+            .types()
+            .into_iter()
+            .map(|field| self.type_layout(&subst.apply(field)))
+            .collect()
+    }
+
+    fn struct_layout(&mut self, st: &RustStruct, args: &[RustType]) -> Result<StructLayout, Error> {
+        let ty = RustType::nominal(st.name().clone().into(), args.to_vec());
+        let repr = Repr::from(st.attrs());
+        if args.len() != st.generics().params.len() {
+            return Err(no_layout(&ty, "generic type"));
+        }
+        let fields = self.field_layouts(st.fields(), st.generics().instantiate(args))?;
+        if repr.transparent {
+            let non_zst = fields
+                .iter()
+                .filter(|field| field.size > 0)
+                .collect::<Vec<_>>();
+            let layout = match non_zst.as_slice() {
+                [field] => **field,
+                [] => Layout { size: 0, align: 1 },
+                _ => return Err(no_layout(&ty, "transparent struct with several fields")),
+            };
+            let offsets = vec![0; fields.len()];
+            let padding = vec![0; fields.len()];
+            return Ok(StructLayout {
+                size: layout.size,
+                align: layout.align,
+                offsets,
+                padding,
+            });
+        }
+        if !repr.c {
+            return Err(no_layout(&ty, "struct without #[repr(C)]"));
+        }
+
+        let mut offsets = vec![];
+        let mut ends = vec![];
+        let mut offset = 0;
+        let mut align = 1;
+        for field in fields.iter() {
+            let field_align = field.align.min(repr.packed.unwrap_or(u64::MAX));
+            offset = round_up(offset, field_align);
+            offsets.push(offset);
+            offset += field.size;
+            ends.push(offset);
+            align = align.max(field_align);
+        }
+        let align = align.max(repr.align.unwrap_or(1));
+        let size = round_up(offset, align);
+        let padding = ends
+            .iter()
+            .zip(offsets.iter().skip(1).chain([&size]))
+            .map(|(end, next)| next - end)
+            .collect();
+        Ok(StructLayout {
+            size,
+            align,
+            offsets,
+            padding,
+        })
+    }
+
+    fn union_layout(&mut self, un: &RustUnion, args: &[RustType]) -> Result<StructLayout, Error> {
+        let ty = RustType::nominal(un.name().clone().into(), args.to_vec());
+        let repr = Repr::from(un.attrs());
+        if !repr.c {
+            return Err(no_layout(&ty, "union without #[repr(C)]"));
+        }
+        if args.len() != un.generics().params.len() {
+            return Err(no_layout(&ty, "generic type"));
+        }
+        let fields = self.field_layouts(un.fields(), un.generics().instantiate(args))?;
+        let align = fields
+            .iter()
+            .map(|field| field.align.min(repr.packed.unwrap_or(u64::MAX)))
+            .chain(repr.align)
+            .max()
+            .unwrap_or(1);
+        let size = round_up(fields.iter().map(|v| v.size).max().unwrap_or(0), align);
+        Ok(StructLayout {
+            size,
+            align,
+            offsets: vec![0; fields.len()],
+            padding: fields.iter().map(|field| size - field.size).collect(),
+        })
+    }
+
+    fn enum_layout(&mut self, ty: &RustType, en: &RustEnum) -> Result<Layout, Error> {
+        if en.variants().iter().any(|v| !v.fields.is_empty()) {
+            return Err(no_layout(ty, "enum with fields"));
+        }
+        let repr = Repr::from(en.attrs());
+        match (repr.int, repr.c) {
+            (Some(int), _) => self.type_layout(&int),
+            // fieldless C enums have the size of an int
+            (None, true) => Ok(Layout::scalar(self.model.int_size(&CIntegralSize::Int))),
+            (None, false) => Err(no_layout(ty, "enum without #[repr(C)] or an integer repr")),
+        }
+    }
+}
+
+impl RustType {
+    /// Computes the size and alignment of a type under a data model, looking up declarations
+    /// in the type context. Fails for types whose layout is not specified by the language
+    pub fn layout(&self, model: DataModel, ctxt: &ProgramTypeContext) -> Result<Layout, Error> {
+        let mut cx = LayoutCx {
+            model,
+            ctxt,
+            visiting: HashSet::new(),
+        };
+        cx.type_layout(self)
+    }
+}
+
+impl RustStruct {
+    /// Computes the layout of a non-generic `#[repr(C)]` (or `#[repr(transparent)]`) struct
+    pub fn layout(
+        &self,
+        model: DataModel,
+        ctxt: &ProgramTypeContext,
+    ) -> Result<StructLayout, Error> {
+        let mut cx = LayoutCx {
+            model,
+            ctxt,
+            visiting: HashSet::new(),
+        };
+        cx.struct_layout(self, &[])
+    }
+}
+
+impl RustUnion {
+    /// Computes the layout of a non-generic `#[repr(C)]` union, every field is at offset 0
+    pub fn layout(
+        &self,
+        model: DataModel,
+        ctxt: &ProgramTypeContext,
+    ) -> Result<StructLayout, Error> {
+        let mut cx = LayoutCx {
+            model,
+            ctxt,
+            visiting: HashSet::new(),
+        };
+        cx.union_layout(self, &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typ::CTypeContextCollector;
+
+    fn context(src: &str) -> ProgramTypeContext {
+        let file = syn::parse_file(src).unwrap();
+        let mut collector = CTypeContextCollector::default();
+        syn::visit::Visit::visit_file(&mut collector, &file);
+        assert!(collector.errors().is_empty());
+        collector.to_type_context()
+    }
+
+    fn ty(src: &str) -> RustType {
+        syn::parse_str::<syn::Type>(src).unwrap().into()
+    }
+
+    #[test]
+    fn test_c_long_depends_on_the_data_model() {
+        let ctxt = context("");
+        let long = ty("libc::c_long");
+        let sizes = [DataModel::LP64, DataModel::ILP32, DataModel::LLP64]
+            .map(|model| long.layout(model, &ctxt).unwrap().size);
+        assert_eq!(sizes, [8, 4, 4]);
+        let longlong = ty("libc::c_ulonglong").layout(DataModel::ILP32, &ctxt);
+        assert_eq!(longlong.unwrap().size, 8);
+    }
+
+    #[test]
+    fn test_repr_c_struct_offsets_and_padding() {
+        let ctxt = context(
+            "
+            #[repr(C)]
+            struct Header { tag: libc::c_char, len: libc::c_long, next: *mut Header, flags: u16 }
+            #[repr(C, packed)]
+            struct Packed { tag: u8, len: u32 }
+            struct Plain { x: i32 }
+            ",
+        );
+        let header = &ctxt.1[&syn::parse_str::<syn::Ident>("Header").unwrap().into()];
+        let layout = header.layout(DataModel::LP64, &ctxt).unwrap();
+        assert_eq!(layout.offsets, vec![0, 8, 16, 24]);
+        assert_eq!(layout.padding, vec![7, 0, 0, 6]);
+        assert_eq!((layout.size, layout.align), (32, 8));
+
+        let layout = header.layout(DataModel::ILP32, &ctxt).unwrap();
+        assert_eq!(layout.offsets, vec![0, 4, 8, 12]);
+        assert_eq!((layout.size, layout.align), (16, 4));
+
+        let packed = ty("Packed").layout(DataModel::LP64, &ctxt).unwrap();
+        assert_eq!(packed, Layout { size: 5, align: 1 });
+        assert!(matches!(
+            ty("Plain").layout(DataModel::LP64, &ctxt),
+            Err(Error::NoLayout { .. })
+        ));
+    }
+}
@@ -8,14 +8,22 @@
 pub extern crate string_cache;
 
 pub mod annotation;
+pub mod antiunify;
+pub mod compat;
+pub mod dependency;
 pub mod error;
 pub mod filesystem;
 pub mod formatter;
+pub mod inference;
 pub mod labelling;
+pub mod layout;
 pub mod location;
 pub mod macros;
 pub mod parser;
+pub mod serialize;
+pub mod traits;
 pub mod typ;
+pub mod unify;
 pub mod wrappers;
 pub mod local_config;
 
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::{
     fmt::Display,
     // fs,
@@ -19,7 +20,7 @@ impl RawLoc {
 }
 
 /// Represents a location with a path and function name
-#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Loc(PathBuf, String);
 
 impl Loc {
@@ -1,12 +1,16 @@
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::{multispace0, u64},
-    sequence::delimited,
+    bytes::complete::{tag, take_while},
+    character::complete::{alpha1, alphanumeric1, multispace0, u64},
+    combinator::{map, opt, recognize, value},
+    multi::{many0, separated_list0, separated_list1},
+    sequence::{delimited, pair, preceded, terminated},
     IResult,
 };
 
-use crate::typ::RustType;
+use crate::typ::{
+    CFloatSize, RustAbi, RustLifetime, RustMutability, RustPath, RustType, RustTypeConstraint, TVar,
+};
 use crate::{labelling::Label, typ::CIntegralSize};
 
 /// A combinator that takes a parser `inner` and produces a parser that also consumes both leading and
@@ -15,148 +19,271 @@ pub fn ws<'a, F: 'a, O, E: nom::error::ParseError<&'a str>>(
     inner: F,
 ) -> impl FnMut(&'a str) -> IResult<&'a str, O, E>
 where
-    F: Fn(&'a str) -> IResult<&'a str, O, E>,
+    F: FnMut(&'a str) -> IResult<&'a str, O, E>,
 {
     delimited(multispace0, inner, multispace0)
 }
 
-pub fn rust_type(s: &str) -> IResult<&str, RustType> {
-    fn int(s: &str) -> IResult<&str, RustType> {
-        tag("c_int")(s).map(|(rest, _)| {
-            (
-                rest,
-                RustType::CInt {
-                    unsigned: false,
-                    size: CIntegralSize::Int,
-                },
-            )
-        })
+/// Parses a lifetime such as `'a` or `'static`
+pub fn lifetime(s: &str) -> IResult<&str, RustLifetime> {
+    let (s, name) = preceded(
+        tag("'"),
+        recognize(many0(alt((alphanumeric1, tag("_"))))),
+    )(s)?;
+    Ok((s, RustLifetime(name.to_string())))
+}
+
+/// Parses an identifier, including raw identifiers such as `r#type`
+fn ident(s: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        opt(tag("r#")),
+        pair(
+            alt((alpha1, tag("_"))),
+            many0(alt((alphanumeric1, tag("_")))),
+        ),
+    ))(s)
+}
+
+fn to_ident(name: &str) -> syn::Ident {
+    let span = proc_macro2::Span::call_site();
+    match name.strip_prefix("r#") {
+        Some(raw) => syn::Ident::new_raw(raw, span),
+        None => syn::Ident::new(name, span),
     }
-    fn ulong(s: &str) -> IResult<&str, RustType> {
-        tag("c_ulong")(s).map(|(rest, _)| {
-            (
-                rest,
-                RustType::CInt {
-                    unsigned: true,
-                    size: CIntegralSize::Long,
-                },
-            )
-        })
+}
+
+/// Parses a path such as `std::rc::Rc`
+pub fn rust_path(s: &str) -> IResult<&str, RustPath> {
+    let (s, segments) = separated_list1(tag("::"), ident)(s)?;
+    Ok((s, RustPath(segments.into_iter().map(to_ident).collect())))
+}
+
+/// Returns the type printed as the word `word`, if any: primitive and C types, along with
+/// type variables `T0..Tn`
+fn keyword(word: &str) -> Option<RustType> {
+    let ty = match word {
+        "never" => RustType::Never,
+        "c_void" => RustType::CVoid,
+        "c_float" => RustType::CFloat(CFloatSize::Float),
+        "c_double" => RustType::CFloat(CFloatSize::Double),
+        "size_t" => RustType::SizeT,
+        "bool" => RustType::Bool,
+        "char" => RustType::Char,
+        "str" => RustType::Str,
+        "i8" => RustType::I8,
+        "i16" => RustType::I16,
+        "i32" => RustType::I32,
+        "i64" => RustType::I64,
+        "i128" => RustType::I128,
+        "isize" => RustType::Isize,
+        "u8" => RustType::U8,
+        "u16" => RustType::U16,
+        "u32" => RustType::U32,
+        "u64" => RustType::U64,
+        "u128" => RustType::U128,
+        "usize" => RustType::Usize,
+        "f32" => RustType::F32,
+        "f64" => RustType::F64,
+        word => {
+            if let Some(name) = word.strip_prefix("c_") {
+                let (unsigned, name) = match name.strip_prefix('u') {
+                    Some(name) => (true, name),
+                    None => (false, name),
+                };
+                let size = match name {
+                    "char" => CIntegralSize::Char,
+                    "short" => CIntegralSize::Short,
+                    "int" => CIntegralSize::Int,
+                    "long" => CIntegralSize::Long,
+                    "longlong" => CIntegralSize::LongLong,
+                    _ => return None,
+                };
+                return Some(RustType::CInt { unsigned, size });
+            }
+            // only the canonical spelling of a type variable, so that `T01` remains an alias
+            let ind = word.strip_prefix('T')?.parse::<usize>().ok()?;
+            if word != TVar(ind).to_string() {
+                return None;
+            }
+            RustType::TVar(TVar(ind))
+        }
+    };
+    Some(ty)
+}
+
+/// Parses a calling convention as printed by `RustAbi`'s `Display`
+fn abi(s: &str) -> IResult<&str, RustAbi> {
+    alt((
+        value(RustAbi::Rust, tag("rust")),
+        map(
+            delimited(tag("extern(\""), take_while(|c| c != '"'), tag("\")")),
+            |name: &str| RustAbi::Extern(Some(name.to_string())),
+        ),
+        value(RustAbi::Extern(None), tag("extern")),
+    ))(s)
+}
+
+/// Parses a comma separated list of types
+fn rust_types(s: &str) -> IResult<&str, Vec<RustType>> {
+    separated_list0(tag(","), ws(rust_type))(s)
+}
+
+/// Parses a trait bound as printed by `RustTypeConstraint`'s `Display`
+pub fn type_constraint(s: &str) -> IResult<&str, RustTypeConstraint> {
+    fn outlives(s: &str) -> IResult<&str, RustTypeConstraint> {
+        map(lifetime, RustTypeConstraint::Outlives)(s)
     }
-    fn void(s: &str) -> IResult<&str, RustType> {
-        tag("c_void")(s).map(|(rest, _)| (rest, RustType::CVoid))
+    fn maybe(s: &str) -> IResult<&str, RustTypeConstraint> {
+        map(preceded(tag("?"), rust_path), RustTypeConstraint::Maybe)(s)
     }
-    fn i32_ty(s: &str) -> IResult<&str, RustType> {
-        tag("i32")(s).map(|(rest, _)| (rest, RustType::I32))
+    fn index(s: &str) -> IResult<&str, RustTypeConstraint> {
+        let (s, mutable) = alt((value(true, tag("IndexMut")), value(false, tag("Index"))))(s)?;
+        let (s, in_ty) = delimited(tag("["), rust_type, tag("]"))(s)?;
+        let (s, out_ty) = preceded(ws(tag("->")), rust_type)(s)?;
+        let constraint = if mutable {
+            RustTypeConstraint::IndexMut(in_ty, out_ty)
+        } else {
+            RustTypeConstraint::Index(in_ty, out_ty)
+        };
+        Ok((s, constraint))
     }
-    fn isize(s: &str) -> IResult<&str, RustType> {
-        tag("isize")(s).map(|(rest, _)| (rest, RustType::Isize))
+    fn sugared(s: &str) -> IResult<&str, RustTypeConstraint> {
+        let (s, path) = rust_path(s)?;
+        let (s, args) = delimited(tag("("), rust_types, tag(")"))(s)?;
+        let (s, out) = preceded(ws(tag("->")), rust_type)(s)?;
+        Ok((s, RustTypeConstraint::Fn(path, args, Box::new(out))))
     }
-    fn usize(s: &str) -> IResult<&str, RustType> {
-        tag("usize")(s).map(|(rest, _)| (rest, RustType::Usize))
+    fn generic(s: &str) -> IResult<&str, RustTypeConstraint> {
+        fn binding(s: &str) -> IResult<&str, (syn::Ident, RustType)> {
+            let (s, name) = terminated(ident, ws(tag("=")))(s)?;
+            let (s, ty) = rust_type(s)?;
+            Ok((s, (to_ident(name), ty)))
+        }
+        let (s, path) = rust_path(s)?;
+        let arg = alt((map(binding, Err), map(rust_type, Ok)));
+        let (s, args) = opt(delimited(
+            tag("<"),
+            separated_list0(tag(","), delimited(multispace0, arg, multispace0)),
+            tag(">"),
+        ))(s)?;
+        let (args, bindings): (Vec<_>, Vec<_>) = args
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|arg| arg.is_ok());
+        let args = args.into_iter().flatten().collect::<Vec<_>>();
+        let bindings = bindings
+            .into_iter()
+            .filter_map(|arg| arg.err())
+            .collect::<Vec<_>>();
+        Ok((s, RustTypeConstraint::Trait(path, args, bindings)))
     }
-    fn size_t(s: &str) -> IResult<&str, RustType> {
-        tag("size_t")(s).map(|(rest, _)| (rest, RustType::Usize))
+    alt((outlives, maybe, index, sugared, generic))(s)
+}
+
+/// Parses a type as printed by `RustType`'s `Display`, so that parsing the printed form of any
+/// type gives back the same type
+pub fn rust_type(s: &str) -> IResult<&str, RustType> {
+    fn pointer(s: &str) -> IResult<&str, RustType> {
+        let (s, mutability) = alt((
+            value(RustMutability::Mutable, tag("mut_ptr_")),
+            value(RustMutability::Immutable, tag("const_ptr_")),
+        ))(s)?;
+        let (s, ty) = rust_type(s)?;
+        Ok((s, RustType::Pointer(mutability, Box::new(ty))))
     }
-    fn uint(s: &str) -> IResult<&str, RustType> {
-        tag("c_uint")(s).map(|(rest, _)| {
-            (
-                rest,
-                RustType::CInt {
-                    unsigned: true,
-                    size: CIntegralSize::Int,
-                },
-            )
-        })
+    fn reference(s: &str) -> IResult<&str, RustType> {
+        let (s, _) = tag("ref")(s)?;
+        let (s, lt) = opt(delimited(tag("<"), lifetime, tag(">")))(s)?;
+        let (s, mutability) = alt((
+            value(RustMutability::Mutable, tag("_mutable_")),
+            value(RustMutability::Immutable, tag("_immutable_")),
+        ))(s)?;
+        let (s, ty) = rust_type(s)?;
+        Ok((s, RustType::Reference(lt, mutability, Box::new(ty))))
     }
-    fn uchar(s: &str) -> IResult<&str, RustType> {
-        tag("c_uchar")(s).map(|(rest, _)| {
-            (
-                rest,
-                RustType::CInt {
-                    unsigned: true,
-                    size: CIntegralSize::Char,
-                },
-            )
-        })
+    fn unary(s: &str) -> IResult<&str, RustType> {
+        let (s, name) = terminated(alt((tag("slice"), tag("option"), tag("vec"))), tag("("))(s)?;
+        let (s, ty) = terminated(ws(rust_type), tag(")"))(s)?;
+        let ty = Box::new(ty);
+        let ty = match name {
+            "slice" => RustType::Slice(ty),
+            "option" => RustType::Option(ty),
+            _ => RustType::Vec(ty),
+        };
+        Ok((s, ty))
     }
-    fn int_ptr(s: &str) -> IResult<&str, RustType> {
-        tag("mut_ptr_c_int")(s).map(|(rest, _)| {
-            (
-                rest,
-                RustType::Pointer(Box::new(RustType::CInt {
-                    unsigned: false,
-                    size: CIntegralSize::Int,
-                })),
-            )
-        })
+    fn array(s: &str) -> IResult<&str, RustType> {
+        let (s, _) = tag("array(")(s)?;
+        let (s, ty) = terminated(ws(rust_type), tag(","))(s)?;
+        let (s, size) = terminated(ws(u64), tag(")"))(s)?;
+        Ok((s, RustType::Array(Box::new(ty), size as usize)))
     }
-    fn uint_ptr(s: &str) -> IResult<&str, RustType> {
-        tag("mut_ptr_c_uint")(s).map(|(rest, _)| {
-            (
-                rest,
-                RustType::Pointer(Box::new(RustType::CInt {
-                    unsigned: true,
-                    size: CIntegralSize::Int,
-                })),
-            )
-        })
+    fn tuple(s: &str) -> IResult<&str, RustType> {
+        let (s, elts) = delimited(tag("tuple("), rust_types, tag(")"))(s)?;
+        Ok((s, RustType::Tuple(elts)))
     }
-    fn uchar_ptr(s: &str) -> IResult<&str, RustType> {
-        tag("mut_ptr_c_uchar")(s).map(|(rest, _)| {
-            (
-                rest,
-                RustType::Pointer(Box::new(RustType::CInt {
-                    unsigned: true,
-                    size: CIntegralSize::Char,
-                })),
-            )
-        })
+    fn adt(s: &str) -> IResult<&str, RustType> {
+        let (s, path) = preceded(tag("adt("), ws(rust_path))(s)?;
+        let (s, _) = pair(tag(","), ws(tag("[")))(s)?;
+        let (s, lifetimes) = many0(terminated(ws(lifetime), opt(tag(","))))(s)?;
+        let (s, args) = terminated(rust_types, pair(tag("]"), ws(tag(")"))))(s)?;
+        Ok((s, RustType::Adt(path, lifetimes, args)))
     }
-    fn void_ptr(s: &str) -> IResult<&str, RustType> {
-        tag("mut_ptr_c_void")(s)
-            .map(|(rest, _)| (rest, RustType::Pointer(Box::new(RustType::CVoid))))
+    fn bounds(s: &str) -> IResult<&str, RustType> {
+        let (s, name) = terminated(alt((tag("dyn"), tag("impl"))), tag("("))(s)?;
+        let (s, bounds) = terminated(separated_list0(tag("+"), ws(type_constraint)), tag(")"))(s)?;
+        let ty = match name {
+            "dyn" => RustType::TraitObject(bounds),
+            _ => RustType::Opaque(bounds),
+        };
+        Ok((s, ty))
     }
-    // not sure how to make wildcard/recursively handle nested pointers so hardcoding it in for now...
-    fn nested_int_ptr(s: &str) -> IResult<&str, RustType> {
-        tag("mut_ptr_mut_ptr_c_int")(s).map(|(rest, _)| {
-            (
-                rest,
-                RustType::Pointer(Box::new(RustType::Pointer(Box::new(RustType::CInt {
-                    unsigned: true,
-                    size: CIntegralSize::Int,
-                })))),
-            )
-        })
+    fn fn_ptr(s: &str) -> IResult<&str, RustType> {
+        let (s, unsafety) = preceded(
+            tag("fn_ptr("),
+            ws(alt((value(true, tag("unsafe")), value(false, tag("safe"))))),
+        )(s)?;
+        let (s, abi) = preceded(tag(","), ws(abi))(s)?;
+        let (s, args) = preceded(tag(","), ws(delimited(tag("["), rust_types, tag("]"))))(s)?;
+        let (s, variadic) = preceded(
+            tag(","),
+            ws(alt((
+                value(true, tag("variadic")),
+                value(false, tag("fixed")),
+            ))),
+        )(s)?;
+        let (s, output) = delimited(tag(","), ws(rust_type), tag(")"))(s)?;
+        let ty = RustType::FnPtr {
+            unsafety,
+            abi,
+            args,
+            variadic,
+            output: Box::new(output),
+        };
+        Ok((s, ty))
     }
-    fn nested_uint_ptr(s: &str) -> IResult<&str, RustType> {
-        tag("mut_ptr_mut_ptr_c_uint")(s).map(|(rest, _)| {
-            (
-                rest,
-                RustType::Pointer(Box::new(RustType::Pointer(Box::new(RustType::CInt {
-                    unsigned: true,
-                    size: CIntegralSize::Int,
-                })))),
-            )
-        })
+    fn alias(s: &str) -> IResult<&str, RustType> {
+        let (s, name) = delimited(tag("alias("), ws(ident), tag(")"))(s)?;
+        Ok((s, RustType::CAlias(to_ident(name))))
+    }
+    fn named(s: &str) -> IResult<&str, RustType> {
+        let (s, word) = ident(s)?;
+        let ty = keyword(word).unwrap_or_else(|| RustType::CAlias(to_ident(word)));
+        Ok((s, ty))
     }
 
     alt((
-        nested_int_ptr,
-        nested_uint_ptr,
-        uint_ptr,
-        uchar_ptr,
-        void_ptr,
-        int_ptr,
-        uint,
-        size_t,
-        uchar,
-        int,
-        ulong,
-        void,
-        i32_ty,
-        isize,
-        usize,
+        pointer,
+        reference,
+        value(RustType::Unit, tag("()")),
+        unary,
+        array,
+        tuple,
+        adt,
+        bounds,
+        fn_ptr,
+        alias,
+        named,
     ))(s)
 }
 
@@ -165,3 +292,178 @@ pub fn label(s: &str) -> IResult<&str, Label> {
     let (s, digits) = u64(s)?;
     Ok((s, Label::of_raw(digits as usize)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn arb_ident() -> impl Strategy<Value = syn::Ident> {
+        "[a-zA-Z_][a-zA-Z0-9_]{0,8}"
+            .prop_filter("not an identifier", |name| name != "_")
+            .prop_map(|name| to_ident(&name))
+    }
+
+    fn arb_path() -> impl Strategy<Value = RustPath> {
+        prop::collection::vec(arb_ident(), 1..3).prop_map(RustPath)
+    }
+
+    fn arb_lifetime() -> impl Strategy<Value = RustLifetime> {
+        "[a-z_][a-z0-9_]{0,4}".prop_map(RustLifetime)
+    }
+
+    fn arb_mutability() -> impl Strategy<Value = RustMutability> {
+        prop_oneof![
+            Just(RustMutability::Mutable),
+            Just(RustMutability::Immutable)
+        ]
+    }
+
+    fn arb_leaf() -> impl Strategy<Value = RustType> {
+        let c_int = (
+            any::<bool>(),
+            prop_oneof![
+                Just(CIntegralSize::Char),
+                Just(CIntegralSize::Short),
+                Just(CIntegralSize::Int),
+                Just(CIntegralSize::Long),
+                Just(CIntegralSize::LongLong),
+            ],
+        )
+            .prop_map(|(unsigned, size)| RustType::CInt { unsigned, size });
+        prop_oneof![
+            prop::sample::select(vec![
+                RustType::CVoid,
+                RustType::CFloat(CFloatSize::Float),
+                RustType::CFloat(CFloatSize::Double),
+                RustType::Unit,
+                RustType::Bool,
+                RustType::Char,
+                RustType::Str,
+                RustType::I8,
+                RustType::I128,
+                RustType::Isize,
+                RustType::U8,
+                RustType::U64,
+                RustType::Usize,
+                RustType::F32,
+                RustType::SizeT,
+                RustType::Never,
+            ]),
+            c_int,
+            (0..20usize).prop_map(|ind| RustType::TVar(TVar(ind))),
+            arb_ident().prop_map(RustType::CAlias),
+            // aliases whose name is also the printed form of another type
+            prop::sample::select(vec!["T3", "c_int", "u8", "mut_ptr_node", "ref_mutable_x"])
+                .prop_map(|name| RustType::CAlias(to_ident(name))),
+        ]
+    }
+
+    fn arb_constraint(ty: BoxedStrategy<RustType>) -> impl Strategy<Value = RustTypeConstraint> {
+        let types = prop::collection::vec(ty.clone(), 0..3);
+        // traits named like the index bounds, whose printed form must not be confused with them
+        let trait_path = prop_oneof![
+            arb_path(),
+            prop::sample::select(vec!["Index", "IndexMut"])
+                .prop_map(|name| RustPath(vec![to_ident(name)])),
+        ];
+        prop_oneof![
+            (ty.clone(), ty.clone()).prop_map(|(i, o)| RustTypeConstraint::Index(i, o)),
+            (ty.clone(), ty.clone()).prop_map(|(i, o)| RustTypeConstraint::IndexMut(i, o)),
+            (
+                trait_path,
+                types.clone(),
+                prop::collection::vec((arb_ident(), ty.clone()), 0..2)
+            )
+                .prop_map(|(path, args, bindings)| RustTypeConstraint::Trait(path, args, bindings)),
+            (arb_path(), types, ty).prop_map(|(path, args, out)| RustTypeConstraint::Fn(
+                path,
+                args,
+                Box::new(out)
+            )),
+            arb_lifetime().prop_map(RustTypeConstraint::Outlives),
+            arb_path().prop_map(RustTypeConstraint::Maybe),
+        ]
+    }
+
+    fn arb_type() -> impl Strategy<Value = RustType> {
+        arb_leaf().prop_recursive(4, 32, 3, |inner| {
+            let types = prop::collection::vec(inner.clone(), 0..3);
+            let bounds = prop::collection::vec(arb_constraint(inner.clone().boxed()), 0..3).boxed();
+            let abi = prop_oneof![
+                Just(RustAbi::Rust),
+                Just(RustAbi::Extern(None)),
+                "[A-Za-z]{1,6}".prop_map(|name| RustAbi::Extern(Some(name))),
+            ];
+            prop_oneof![
+                (inner.clone(), 0..64usize)
+                    .prop_map(|(ty, size)| RustType::Array(Box::new(ty), size)),
+                inner.clone().prop_map(|ty| RustType::Slice(Box::new(ty))),
+                inner.clone().prop_map(|ty| RustType::Option(Box::new(ty))),
+                inner.clone().prop_map(|ty| RustType::Vec(Box::new(ty))),
+                types.clone().prop_map(RustType::Tuple),
+                (
+                    arb_path(),
+                    prop::collection::vec(arb_lifetime(), 0..2),
+                    types.clone()
+                )
+                    .prop_map(|(path, lifetimes, args)| RustType::Adt(path, lifetimes, args)),
+                (arb_mutability(), inner.clone())
+                    .prop_map(|(m, ty)| RustType::Pointer(m, Box::new(ty))),
+                (
+                    prop::option::of(arb_lifetime()),
+                    arb_mutability(),
+                    inner.clone()
+                )
+                    .prop_map(|(lt, m, ty)| RustType::Reference(
+                        lt,
+                        m,
+                        Box::new(ty)
+                    )),
+                (any::<bool>(), abi, types, any::<bool>(), inner).prop_map(
+                    |(unsafety, abi, args, variadic, output)| RustType::FnPtr {
+                        unsafety,
+                        abi,
+                        args,
+                        variadic,
+                        output: Box::new(output),
+                    }
+                ),
+                bounds.clone().prop_map(RustType::TraitObject),
+                bounds.prop_map(RustType::Opaque),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_display_of_types_parses_back(ty in arb_type()) {
+            let txt = format!("{}", ty);
+            prop_assert_eq!(rust_type(&txt), Ok(("", ty)), "{}", txt);
+        }
+    }
+
+    #[test]
+    fn test_words_are_only_read_as_a_whole() {
+        assert_eq!(
+            rust_type("u8x"),
+            Ok(("", RustType::CAlias(to_ident("u8x"))))
+        );
+        assert_eq!(
+            rust_type("T01"),
+            Ok(("", RustType::CAlias(to_ident("T01"))))
+        );
+        let int = RustType::CInt {
+            unsigned: false,
+            size: CIntegralSize::Int,
+        };
+        let nested = (0..4).fold(int, |ty, _| {
+            RustType::Pointer(RustMutability::Mutable, Box::new(ty))
+        });
+        assert_eq!(
+            rust_type("mut_ptr_mut_ptr_mut_ptr_mut_ptr_c_int"),
+            Ok(("", nested))
+        );
+        assert_eq!(rust_type("size_t"), Ok(("", RustType::SizeT)));
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::Error;
+use crate::location::Loc;
+use crate::typ::{
+    ProgramTypeContext, RustEnum, RustPath, RustScope, RustStruct, RustType, RustTypeSignature,
+    RustUnion, TypeMap,
+};
+
+/// Version of the on-disk schema, bumped on every incompatible change to the serialized types
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Envelope around every serialized value, recording the schema it was written with
+#[derive(Serialize, Deserialize)]
+struct Versioned<T> {
+    version: u32,
+    data: T,
+}
+
+/// Serializes a value to JSON, tagged with the current schema version
+pub fn to_json<T: Serialize>(data: &T) -> Result<String, Error> {
+    let versioned = Versioned {
+        version: SCHEMA_VERSION,
+        data,
+    };
+    Ok(serde_json::to_string(&versioned)?)
+}
+
+/// Deserializes a value written by `to_json`, rejecting other schema versions
+pub fn from_json<T: DeserializeOwned>(json: &str) -> Result<T, Error> {
+    #[derive(Deserialize)]
+    struct Version {
+        version: u32,
+    }
+    let Version { version } = serde_json::from_str(json)?;
+    if version != SCHEMA_VERSION {
+        return Err(Error::SchemaVersion(version));
+    }
+    let versioned: Versioned<T> = serde_json::from_str(json)?;
+    Ok(versioned.data)
+}
+
+/// `ProgramTypeContext` with named fields, so the schema does not depend on the tuple order
+#[derive(Serialize, Deserialize)]
+struct TypeContextSchema {
+    aliases: HashMap<RustPath, RustType>,
+    structs: HashMap<RustPath, RustStruct>,
+    enums: HashMap<RustPath, RustEnum>,
+    unions: HashMap<RustPath, RustUnion>,
+    modules: HashMap<RustPath, RustScope>,
+}
+
+pub fn type_context_to_json(ctxt: &ProgramTypeContext) -> Result<String, Error> {
+    let (aliases, structs, enums, unions, modules) = ctxt.clone();
+    to_json(&TypeContextSchema {
+        aliases,
+        structs,
+        enums,
+        unions,
+        modules,
+    })
+}
+
+pub fn type_context_from_json(json: &str) -> Result<ProgramTypeContext, Error> {
+    let ctxt: TypeContextSchema = from_json(json)?;
+    Ok((
+        ctxt.aliases,
+        ctxt.structs,
+        ctxt.enums,
+        ctxt.unions,
+        ctxt.modules,
+    ))
+}
+
+/// `Loc`s are not strings, so type maps are stored as a list of entries
+pub fn type_map_to_json(types: &TypeMap) -> Result<String, Error> {
+    let mut entries = types.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(loc, _)| *loc);
+    to_json(&entries)
+}
+
+pub fn type_map_from_json(json: &str) -> Result<TypeMap, Error> {
+    let entries: Vec<(Loc, RustTypeSignature)> = from_json(json)?;
+    Ok(entries.into_iter().collect())
+}
+
+fn parse_ident<E: serde::de::Error>(name: &str) -> Result<syn::Ident, E> {
+    use syn::ext::IdentExt;
+    // `parse_any` also accepts keywords, which appear in paths such as `super::T`
+    syn::parse::Parser::parse_str(syn::Ident::parse_any, name)
+        .map_err(|_| E::custom(format!("invalid identifier {:?}", name)))
+}
+
+impl Serialize for RustPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for RustPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let path = String::deserialize(deserializer)?;
+        if path.is_empty() {
+            return Ok(RustPath(vec![]));
+        }
+        path.split("::")
+            .map(parse_ident)
+            .collect::<Result<_, _>>()
+            .map(RustPath)
+    }
+}
+
+/// `#[serde(with = ..)]` helpers for the identifiers stored in the type model
+pub(crate) mod ident {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(ident: &syn::Ident, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(ident)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<syn::Ident, D::Error> {
+        parse_ident(&String::deserialize(deserializer)?)
+    }
+}
+
+pub(crate) mod ident_pairs {
+    use super::*;
+
+    pub fn serialize<S: Serializer, T: Serialize>(
+        pairs: &[(syn::Ident, T)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(pairs.iter().map(|(id, v)| (id.to_string(), v)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(syn::Ident, T)>, D::Error> {
+        Vec::<(String, T)>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(id, v)| Ok((parse_ident(&id)?, v)))
+            .collect()
+    }
+}
+
+pub(crate) mod ident_set {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        set: &HashSet<syn::Ident>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(set.iter().map(|id| id.to_string()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashSet<syn::Ident>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|id| parse_ident(id))
+            .collect()
+    }
+}
+
+pub(crate) mod ident_triples {
+    use super::*;
+
+    pub fn serialize<S: Serializer, A: Serialize, B: Serialize>(
+        triples: &[(syn::Ident, A, B)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(triples.iter().map(|(id, a, b)| (id.to_string(), a, b)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, A: Deserialize<'de>, B: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(syn::Ident, A, B)>, D::Error> {
+        Vec::<(String, A, B)>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(id, a, b)| Ok((parse_ident(&id)?, a, b)))
+            .collect()
+    }
+}
+
+pub(crate) mod ident_map {
+    use super::*;
+
+    pub fn serialize<S: Serializer, T: Serialize>(
+        map: &HashMap<syn::Ident, T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(map.iter().map(|(id, v)| (id.to_string(), v)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<syn::Ident, T>, D::Error> {
+        HashMap::<String, T>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(id, v)| Ok((parse_ident(&id)?, v)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typ::CTypeContextCollector;
+
+    #[test]
+    fn test_type_context_round_trips_through_json() {
+        let file = syn::parse_file(
+            "
+            mod inner {
+                use super::Shape as S;
+                #[repr(C)]
+                pub struct Node<'a, T> { next: *mut Node<'a, T>, shape: S, r#type: &'a T }
+            }
+            pub enum Shape { Circle(f64), Square { side: libc::c_int } = 4 }
+            pub union Bits { raw: u32, half: [u16; 2] }
+            type Callback = unsafe extern \"C\" fn(*mut libc::c_void, ...) -> i32;
+            ",
+        )
+        .unwrap();
+        let mut collector = CTypeContextCollector::default();
+        syn::visit::Visit::visit_file(&mut collector, &file);
+        assert!(collector.errors().is_empty(), "{:?}", collector.errors());
+        let ctxt = collector.to_type_context();
+        let path = |src: &str| RustPath::from(&syn::parse_str::<syn::Path>(src).unwrap());
+        assert!(ctxt.0.contains_key(&path("Callback")));
+        assert!(ctxt.1.contains_key(&path("inner::Node")));
+        assert!(ctxt.2.contains_key(&path("Shape")));
+        assert!(ctxt.3.contains_key(&path("Bits")));
+
+        let json = type_context_to_json(&ctxt).unwrap();
+        assert_eq!(type_context_from_json(&json).unwrap(), ctxt);
+    }
+
+    #[test]
+    fn test_type_maps_round_trip_and_versions_are_checked() {
+        let sig = syn::parse_str::<syn::Signature>(
+            "fn f<'a, T0: Index<usize, Output = u8>>(x: &'a T0) -> Option<T0>",
+        );
+        let loc = Loc::from((
+            crate::location::RawLoc::new("src/lib.rs".into(), vec![1]),
+            "crate::f".to_string(),
+        ));
+        let types: TypeMap = [(loc, sig.unwrap().into())].into_iter().collect();
+
+        let json = type_map_to_json(&types).unwrap();
+        let decoded = type_map_from_json(&json).unwrap();
+        let (sig, decoded_sig) = (types.values().next(), decoded.values().next());
+        assert_eq!(
+            format!("{}", sig.unwrap()),
+            format!("{}", decoded_sig.unwrap())
+        );
+
+        let version = format!("\"version\":{}", SCHEMA_VERSION);
+        let stale = json.replacen(&version, "\"version\":0", 1);
+        assert!(matches!(
+            type_map_from_json(&stale),
+            Err(Error::SchemaVersion(0))
+        ));
+    }
+}
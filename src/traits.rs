@@ -0,0 +1,628 @@
+use crate::typ::{
+    Error, ProgramTypeContext, RustAbi, RustGenerics, RustMutability, RustPath, RustType,
+    RustTypeConstraint, TVar,
+};
+use crate::unify::{Substitution, Unifier};
+
+/// Maximum nesting of impl bounds explored while resolving a bound, guarding against blanket
+/// impls that would otherwise be unfolded forever
+const MAX_DEPTH: usize = 16;
+
+/// Impls from the standard library known to the engine, written as they would be in a crate.
+/// Raw pointers only support indexing once wrapped by an `IndexWrapper`.
+const BUILTIN_IMPLS: &str = "
+    impl<T> Index<usize> for Vec<T> { type Output = T; }
+    impl<T> IndexMut<usize> for Vec<T> { type Output = T; }
+    impl<T> Index<usize> for [T] { type Output = T; }
+    impl<T> IndexMut<usize> for [T] { type Output = T; }
+    impl<T> Index<usize> for chrusty::IndexWrapperBase<*mut T> { type Output = T; }
+    impl<T> IndexMut<usize> for chrusty::IndexWrapperBase<*mut T> { type Output = T; }
+    impl<T> Index<usize> for chrusty::IndexWrapperBase<*const T> { type Output = T; }
+    impl<T> Deref for Vec<T> { type Target = [T]; }
+    impl<T> DerefMut for Vec<T> { type Target = [T]; }
+    impl Deref for String { type Target = str; }
+    impl<T: ?Sized> Deref for Box<T> { type Target = T; }
+    impl<T: ?Sized> DerefMut for Box<T> { type Target = T; }
+    impl<T: ?Sized> Deref for &T { type Target = T; }
+    impl<T: ?Sized> Deref for &mut T { type Target = T; }
+    impl<T: ?Sized> DerefMut for &mut T { type Target = T; }
+    impl<T> IntoIterator for Vec<T> { type Item = T; }
+    impl<T: Clone> Clone for Vec<T> {}
+    impl Clone for String {}
+    impl<T: Clone> Clone for Box<T> {}
+    impl<T: Clone> Clone for Option<T> {}
+    impl<T: Copy> Copy for Option<T> {}
+";
+
+/// An impl of a trait for a type. The generic parameters of the impl are `TVar(0)..TVar(n)` in
+/// the other fields, and the associated types it defines are kept as bindings of the trait.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RustImpl {
+    /// bounds on each generic parameter of the impl
+    pub params: Vec<Vec<RustTypeConstraint>>,
+    pub self_ty: RustType,
+    pub trait_path: RustPath,
+    pub trait_args: Vec<RustType>,
+    /// associated types, e.g `Output` for `Index`
+    pub assoc: Vec<(syn::Ident, RustType)>,
+}
+
+/// A bound in the uniform shape `P<A..>` along with its associated type bindings, so that
+/// `Index<T1, Output = T2>` and `Fn(A, B) -> C` (i.e `Fn<(A, B), Output = C>`) can be matched
+/// against impls like any other trait
+struct TraitRef {
+    path: RustPath,
+    args: Vec<RustType>,
+    bindings: Vec<(syn::Ident, RustType)>,
+}
+
+fn ident(name: &str) -> syn::Ident {
+    syn::Ident::new(name, proc_macro2::Span::call_site())
+}
+
+/// Returns the uniform shape of a bound, None for lifetime and `?Sized` bounds
+fn trait_ref(bound: &RustTypeConstraint) -> Option<TraitRef> {
+    match bound {
+        RustTypeConstraint::Index(t1, t2) | RustTypeConstraint::IndexMut(t1, t2) => {
+            let name = match bound {
+                RustTypeConstraint::Index(..) => "Index",
+                _ => "IndexMut",
+            };
+            Some(TraitRef {
+                path: ident(name).into(),
+                args: vec![t1.clone()],
+                bindings: vec![(ident("Output"), t2.clone())],
+            })
+        }
+        RustTypeConstraint::Trait(path, args, bindings) => Some(TraitRef {
+            path: path.clone(),
+            args: args.clone(),
+            bindings: bindings.clone(),
+        }),
+        RustTypeConstraint::Fn(path, args, out) => Some(TraitRef {
+            path: path.clone(),
+            args: vec![RustType::Tuple(args.clone())],
+            bindings: vec![(ident("Output"), (**out).clone())],
+        }),
+        RustTypeConstraint::Outlives(_) | RustTypeConstraint::Maybe(_) => None,
+    }
+}
+
+/// Rebuilds `bound` with the associated types it resolved to
+fn with_bindings(
+    bound: &RustTypeConstraint,
+    bindings: Vec<(syn::Ident, RustType)>,
+) -> RustTypeConstraint {
+    let output = bindings
+        .iter()
+        .find(|(name, _)| name == "Output")
+        .map(|(_, ty)| ty.clone());
+    match (bound, output) {
+        (RustTypeConstraint::Index(t1, _), Some(out)) => RustTypeConstraint::Index(t1.clone(), out),
+        (RustTypeConstraint::IndexMut(t1, _), Some(out)) => {
+            RustTypeConstraint::IndexMut(t1.clone(), out)
+        }
+        (RustTypeConstraint::Fn(path, args, _), Some(out)) => {
+            RustTypeConstraint::Fn(path.clone(), args.clone(), Box::new(out))
+        }
+        // written as `Index<T1>`, parsed into the dedicated variants once `Output` is known
+        (RustTypeConstraint::Trait(path, args, _), Some(out))
+            if path.segments().len() == 1 && args.len() == 1 && bindings.len() == 1 =>
+        {
+            match path.name().to_string().as_str() {
+                "Index" => RustTypeConstraint::Index(args[0].clone(), out),
+                "IndexMut" => RustTypeConstraint::IndexMut(args[0].clone(), out),
+                _ => RustTypeConstraint::Trait(path.clone(), args.clone(), bindings),
+            }
+        }
+        (RustTypeConstraint::Trait(path, args, _), _) => {
+            RustTypeConstraint::Trait(path.clone(), args.clone(), bindings)
+        }
+        (bound, _) => bound.clone(),
+    }
+}
+
+fn max_var(ty: &RustType) -> Option<usize> {
+    match ty {
+        RustType::TVar(TVar(ind)) => Some(*ind),
+        ty => ty.children().into_iter().filter_map(max_var).max(),
+    }
+}
+
+/// Returns true if `t1` and `t2` are the same type, without binding any type variable
+fn same_type(t1: &RustType, t2: &RustType) -> bool {
+    crate::unify::unify(t1, t2).is_ok_and(|(_, subst)| subst.is_empty())
+}
+
+/// Table of trait impls answering whether types satisfy bounds. Traits are identified by the
+/// last segment of their path, so that `Index` and `std::ops::Index` are the same trait.
+#[derive(Clone, Debug, Default)]
+pub struct TraitEnv {
+    impls: Vec<RustImpl>,
+}
+
+impl TraitEnv {
+    pub fn new() -> Self {
+        TraitEnv::default()
+    }
+
+    /// Returns an environment with the impls of the standard library the engine knows about
+    pub fn builtin() -> Self {
+        let file = syn::parse_file(BUILTIN_IMPLS).unwrap();
+        let mut collector = ImplCollector::default();
+        syn::visit::Visit::visit_file(&mut collector, &file);
+        assert!(collector.errors().is_empty(), "invalid built-in impls");
+        TraitEnv {
+            impls: collector.to_impls(&Default::default()),
+        }
+    }
+
+    pub fn impls(&self) -> &Vec<RustImpl> {
+        &self.impls
+    }
+
+    pub fn add_impl(&mut self, imp: RustImpl) {
+        self.impls.push(imp)
+    }
+
+    /// Adds the impls generated by the `#[derive(..)]` attributes of the declarations of a type
+    /// context, bounding every type parameter by the derived trait as `derive` does
+    pub fn add_derives(&mut self, ctxt: &ProgramTypeContext) {
+        let decls = ctxt
+            .1
+            .iter()
+            .map(|(path, st)| (path, st.generics(), st.attrs()))
+            .chain(
+                ctxt.2
+                    .iter()
+                    .map(|(path, en)| (path, en.generics(), en.attrs())),
+            )
+            .chain(
+                ctxt.3
+                    .iter()
+                    .map(|(path, un)| (path, un.generics(), un.attrs())),
+            );
+        for (path, generics, attrs) in decls {
+            let args = (0..generics.params.len())
+                .map(|ind| RustType::TVar(TVar(ind)))
+                .collect();
+            let self_ty = RustType::nominal(path.clone(), args);
+            for derive in attrs.derives.iter() {
+                let bound = RustTypeConstraint::Trait(derive.clone(), vec![], vec![]);
+                self.impls.push(RustImpl {
+                    params: vec![vec![bound]; generics.params.len()],
+                    self_ty: self_ty.clone(),
+                    trait_path: derive.clone(),
+                    trait_args: vec![],
+                    assoc: vec![],
+                })
+            }
+        }
+    }
+
+    /// Returns true if `ty` satisfies `bound`. Type variables in `ty` and `bound` stand for
+    /// unknown types, which only satisfy bounds that hold for any type.
+    pub fn satisfies(&self, ty: &RustType, bound: &RustTypeConstraint) -> bool {
+        self.resolve(ty, bound).is_some()
+    }
+
+    /// Resolves `bound` for `ty`, returning the bound with the associated types defined by the
+    /// selected impl, e.g `Index<usize, Output = u8>` for `Vec<u8>: Index<usize>`. Lifetime and
+    /// `?Sized` bounds are always satisfied.
+    pub fn resolve(&self, ty: &RustType, bound: &RustTypeConstraint) -> Option<RustTypeConstraint> {
+        self.resolve_at(ty, bound, 0)
+    }
+
+    /// Returns the associated type `name` of the impl of `bound` for `ty`
+    pub fn associated_type(
+        &self,
+        ty: &RustType,
+        bound: &RustTypeConstraint,
+        name: &str,
+    ) -> Option<RustType> {
+        let query = trait_ref(bound)?;
+        let bindings = self.bindings_at(ty, &query, 0)?;
+        bindings
+            .into_iter()
+            .find(|(assoc, _)| assoc == name)
+            .map(|(_, ty)| ty)
+    }
+
+    /// Checks the bounds of the type parameters of a signature, once instantiated by `subst`.
+    /// Parameters left unbound by `subst` are not checked.
+    pub fn check(
+        &self,
+        constraints: &[(TVar, Vec<RustTypeConstraint>)],
+        subst: &Substitution,
+    ) -> Result<(), Error> {
+        for (var, bounds) in constraints.iter() {
+            let ty = subst.apply(&RustType::TVar(*var));
+            if ty == RustType::TVar(*var) {
+                continue;
+            }
+            for bound in bounds.iter() {
+                let mut bound = bound.clone();
+                for arg in bound.types_mut() {
+                    *arg = subst.apply(arg)
+                }
+                if !self.satisfies(&ty, &bound) {
+                    return Err(Error::Unsatisfied {
+                        ty: Box::new(ty),
+                        bound: Box::new(bound),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_at(
+        &self,
+        ty: &RustType,
+        bound: &RustTypeConstraint,
+        depth: usize,
+    ) -> Option<RustTypeConstraint> {
+        let Some(query) = trait_ref(bound) else {
+            return Some(bound.clone());
+        };
+        let mut bindings = self.bindings_at(ty, &query, depth)?;
+        // the associated types written in the bound must be the ones the impl defines
+        for (name, expected) in query.bindings.iter() {
+            match bindings.iter().find(|(assoc, _)| assoc == name) {
+                Some((_, actual)) if same_type(expected, actual) => (),
+                Some(_) => return None,
+                None => bindings.push((name.clone(), expected.clone())),
+            }
+        }
+        Some(with_bindings(bound, bindings))
+    }
+
+    /// Returns the associated types of the impl of `query` for `ty`, if there is one
+    fn bindings_at(
+        &self,
+        ty: &RustType,
+        query: &TraitRef,
+        depth: usize,
+    ) -> Option<Vec<(syn::Ident, RustType)>> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        if let Some(bindings) = self.builtin_bindings(ty, query, depth) {
+            return Some(bindings);
+        }
+        if let Some(bindings) = self
+            .impls
+            .iter()
+            .find_map(|imp| self.match_impl(imp, ty, query, depth))
+        {
+            return Some(bindings);
+        }
+        match ty {
+            // arrays are indexed like the slices they coerce to
+            RustType::Array(elt, _) if ["Index", "IndexMut"].contains(&&*name_of(query)) => {
+                self.bindings_at(&RustType::Slice(elt.clone()), query, depth + 1)
+            }
+            _ => None,
+        }
+    }
+
+    /// Impls that cannot be written as a `BUILTIN_IMPLS` entry, as they range over every
+    /// primitive, tuple, array or fn pointer type
+    fn builtin_bindings(
+        &self,
+        ty: &RustType,
+        query: &TraitRef,
+        depth: usize,
+    ) -> Option<Vec<(syn::Ident, RustType)>> {
+        let bound = RustTypeConstraint::Trait(query.path.clone(), vec![], vec![]);
+        let holds = match (&*name_of(query), ty) {
+            ("Sized", ty) => !matches!(
+                ty,
+                RustType::Str | RustType::Slice(_) | RustType::TraitObject(_)
+            ),
+            ("Copy" | "Clone", ty) if ty.is_primitive() => *ty != RustType::Str,
+            (
+                "Copy" | "Clone",
+                RustType::CInt { .. }
+                | RustType::CFloat(_)
+                | RustType::SizeT
+                | RustType::Pointer(..)
+                | RustType::FnPtr { .. }
+                | RustType::Reference(_, RustMutability::Immutable, _),
+            ) => true,
+            ("Copy" | "Clone", RustType::Tuple(elts)) => elts
+                .iter()
+                .all(|elt| self.resolve_at(elt, &bound, depth + 1).is_some()),
+            ("Copy" | "Clone", RustType::Array(elt, _)) => {
+                self.resolve_at(elt, &bound, depth + 1).is_some()
+            }
+            (
+                "Fn" | "FnMut" | "FnOnce",
+                RustType::FnPtr {
+                    unsafety: false,
+                    abi: RustAbi::Rust,
+                    args,
+                    variadic: false,
+                    output,
+                },
+            ) => {
+                let args = RustType::Tuple(args.clone());
+                if query.args.len() == 1 && same_type(&query.args[0], &args) {
+                    return Some(vec![(ident("Output"), (**output).clone())]);
+                }
+                false
+            }
+            _ => false,
+        };
+        holds.then(Vec::new)
+    }
+
+    fn match_impl(
+        &self,
+        imp: &RustImpl,
+        ty: &RustType,
+        query: &TraitRef,
+        depth: usize,
+    ) -> Option<Vec<(syn::Ident, RustType)>> {
+        if imp.trait_path.name() != query.path.name() || imp.trait_args.len() != query.args.len() {
+            return None;
+        }
+        // rename the parameters of the impl apart from the variables of the query, and out of
+        // the domain of the renaming itself since substitutions follow chains of bindings
+        let offset = std::iter::once(ty)
+            .chain(query.args.iter())
+            .filter_map(max_var)
+            .map(|max| max + 1)
+            .chain([imp.params.len()])
+            .max()
+            .unwrap();
+        let rename: Substitution = (0..imp.params.len())
+            .map(|ind| (TVar(ind), RustType::TVar(TVar(ind + offset))))
+            .collect();
+        let mut unifier = Unifier::new();
+        unifier.unify(&rename.apply(&imp.self_ty), ty).ok()?;
+        for (arg, expected) in imp.trait_args.iter().zip(query.args.iter()) {
+            unifier.unify(&rename.apply(arg), expected).ok()?;
+        }
+        let subst = unifier.into_substitution();
+        // the variables of the query are unknown types, which the impl may not pick
+        if subst.iter().any(|(var, _)| var.0 < offset) {
+            return None;
+        }
+        let instantiate = |ty: &RustType| subst.apply(&rename.apply(ty));
+        for (ind, bounds) in imp.params.iter().enumerate() {
+            let param = instantiate(&RustType::TVar(TVar(ind)));
+            if param == RustType::TVar(TVar(ind + offset)) {
+                continue;
+            }
+            for bound in bounds.iter() {
+                let mut bound = bound.clone();
+                for arg in bound.types_mut() {
+                    *arg = instantiate(arg)
+                }
+                self.resolve_at(&param, &bound, depth + 1)?;
+            }
+        }
+        let bindings = imp
+            .assoc
+            .iter()
+            .map(|(name, ty)| (name.clone(), instantiate(ty)))
+            .collect();
+        Some(bindings)
+    }
+}
+
+fn name_of(query: &TraitRef) -> String {
+    query.path.name().to_string()
+}
+
+/// Collects the trait impls of a crate, see `TraitEnv`
+#[derive(Debug, Default, Clone)]
+pub struct ImplCollector {
+    /// impls along with the module they were written in
+    impls: Vec<(RustPath, RustImpl)>,
+    module: RustPath,
+    errors: Vec<Error>,
+}
+
+impl ImplCollector {
+    /// Sets the module that the items visited next are declared in, see
+    /// `CTypeContextCollector::set_module`
+    pub fn set_module(&mut self, module: RustPath) {
+        self.module = module;
+    }
+
+    /// Returns the impls, with the types they mention qualified by the module of the impl
+    pub fn to_impls(self, ctxt: &ProgramTypeContext) -> Vec<RustImpl> {
+        self.impls
+            .into_iter()
+            .map(|(module, mut imp)| {
+                let types = std::iter::once(&mut imp.self_ty)
+                    .chain(imp.trait_args.iter_mut())
+                    .chain(imp.assoc.iter_mut().map(|(_, ty)| ty))
+                    .chain(imp.params.iter_mut().flatten().flat_map(|v| v.types_mut()));
+                for ty in types {
+                    ty.qualify(&module, ctxt)
+                }
+                imp
+            })
+            .collect()
+    }
+
+    /// Returns the errors for every impl that was skipped during collection
+    pub fn errors(&self) -> &Vec<Error> {
+        &self.errors
+    }
+}
+
+/// Replaces `Self` in a type written inside an impl by the type being implemented
+pub(crate) fn replace_self(ty: &mut RustType, self_ty: &RustType) {
+    match ty {
+        RustType::CAlias(id) if id == "Self" => *ty = self_ty.clone(),
+        ty => {
+            for child in ty.children_mut() {
+                replace_self(child, self_ty)
+            }
+        }
+    }
+}
+
+impl TryFrom<&syn::ItemImpl> for RustImpl {
+    type Error = Error;
+
+    fn try_from(i: &syn::ItemImpl) -> Result<Self, Self::Error> {
+        let path = match &i.trait_ {
+            Some((None, path, _)) => path,
+            _ => return Err(Error::unsupported("impl", &i.self_ty)),
+        };
+        let generics = RustGenerics::try_from(&i.generics)?;
+        let mut self_ty = RustType::try_from(&*i.self_ty)?;
+        generics.abstract_params(&mut self_ty);
+        let bound = syn::TypeParamBound::Trait(syn::TraitBound {
+            paren_token: None,
+            modifier: syn::TraitBoundModifier::None,
+            lifetimes: None,
+            path: path.clone(),
+        });
+        let mut trait_ref = trait_ref(&RustTypeConstraint::try_from(&bound)?).unwrap();
+        for item in i.items.iter() {
+            if let syn::ImplItem::Type(syn::ImplItemType { ident, ty, .. }) = item {
+                trait_ref.bindings.push((ident.clone(), ty.try_into()?))
+            }
+        }
+        let types = trait_ref
+            .args
+            .iter_mut()
+            .chain(trait_ref.bindings.iter_mut().map(|(_, ty)| ty));
+        for ty in types {
+            generics.abstract_params(ty);
+            replace_self(ty, &self_ty)
+        }
+        Ok(RustImpl {
+            params: generics
+                .params
+                .into_iter()
+                .map(|(_, bounds)| bounds)
+                .collect(),
+            self_ty,
+            trait_path: trait_ref.path,
+            trait_args: trait_ref.args,
+            assoc: trait_ref.bindings,
+        })
+    }
+}
+
+impl<'ast> syn::visit::Visit<'ast> for ImplCollector {
+    fn visit_item_mod(&mut self, i: &'ast syn::ItemMod) {
+        let parent = self.module.clone();
+        self.module = parent.join(std::slice::from_ref(&i.ident));
+        syn::visit::visit_item_mod(self, i);
+        self.module = parent;
+    }
+
+    fn visit_item_impl(&mut self, i: &'ast syn::ItemImpl) {
+        // inherent impls implement no trait
+        if i.trait_.is_none() {
+            return;
+        }
+        match RustImpl::try_from(i) {
+            Ok(imp) => self.impls.push((self.module.clone(), imp)),
+            Err(err) => self.errors.push(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typ::CTypeContextCollector;
+    use crate::CHRusty_parse;
+
+    fn ty(src: &str) -> RustType {
+        CHRusty_parse!(src as syn::Type).into()
+    }
+
+    fn bound(src: &str) -> RustTypeConstraint {
+        CHRusty_parse!(src as syn::TypeParamBound).into()
+    }
+
+    #[test]
+    fn test_builtin_impls_answer_queries_and_compute_outputs() {
+        let env = TraitEnv::builtin();
+        assert_eq!(
+            env.resolve(&ty("Vec<i32>"), &bound("Index<usize>")),
+            Some(bound("Index<usize, Output = i32>"))
+        );
+        assert!(env.satisfies(&ty("Vec<i32>"), &bound("Index<usize, Output = i32>")));
+        assert!(!env.satisfies(&ty("Vec<i32>"), &bound("Index<usize, Output = u8>")));
+        assert!(env.satisfies(&ty("[u8; 4]"), &bound("IndexMut<usize, Output = u8>")));
+        assert!(!env.satisfies(&ty("*mut u8"), &bound("Index<usize>")));
+        assert_eq!(
+            env.associated_type(&ty("Box<[u8]>"), &bound("std::ops::Deref"), "Target"),
+            Some(ty("[u8]"))
+        );
+
+        assert!(env.satisfies(&ty("(i32, *const u8, [char; 2])"), &bound("Copy")));
+        assert!(!env.satisfies(&ty("(i32, Vec<u8>)"), &bound("Copy")));
+        assert!(env.satisfies(&ty("Option<Vec<u8>>"), &bound("Clone")));
+        assert!(!env.satisfies(&ty("[u8]"), &bound("Sized")));
+        assert!(env.satisfies(&ty("[u8]"), &bound("?Sized")));
+        assert!(env.satisfies(&ty("fn(u8) -> bool"), &bound("FnMut(u8) -> bool")));
+        assert!(!env.satisfies(&ty("unsafe fn(u8) -> bool"), &bound("Fn(u8) -> bool")));
+        // nothing is known about a type variable
+        assert!(!env.satisfies(&RustType::TVar(TVar(0)), &bound("Copy")));
+    }
+
+    #[test]
+    fn test_crate_impls_and_derives_are_resolved() {
+        let file = syn::parse_file(
+            "
+            mod grid {
+                #[derive(Clone, Copy)]
+                pub struct Cell<T> { value: T }
+                pub struct Grid<T> { cells: Vec<Cell<T>> }
+                impl<T: Copy> std::ops::Index<(usize, usize)> for Grid<T> {
+                    type Output = Cell<T>;
+                }
+            }
+            impl Iterator for grid::Grid<u8> { type Item = Self; }
+            ",
+        )
+        .unwrap();
+        let mut types = CTypeContextCollector::default();
+        syn::visit::Visit::visit_file(&mut types, &file);
+        let ctxt = types.to_type_context();
+        let mut impls = ImplCollector::default();
+        syn::visit::Visit::visit_file(&mut impls, &file);
+        assert!(impls.errors().is_empty());
+
+        let mut env = TraitEnv::builtin();
+        for imp in impls.to_impls(&ctxt) {
+            env.add_impl(imp)
+        }
+        env.add_derives(&ctxt);
+
+        let grid = |elt: &str| ty(&format!("grid::Grid<{}>", elt));
+        let index = bound("Index<(usize, usize)>");
+        assert_eq!(
+            env.associated_type(&grid("i32"), &index, "Output"),
+            Some(ty("grid::Cell<i32>"))
+        );
+        assert!(!env.satisfies(&grid("String"), &index));
+        assert!(env.satisfies(&ty("grid::Cell<i32>"), &bound("Copy")));
+        assert!(!env.satisfies(&ty("grid::Cell<Vec<i32>>"), &bound("Copy")));
+        assert_eq!(
+            env.associated_type(&grid("u8"), &bound("Iterator"), "Item"),
+            Some(grid("u8"))
+        );
+
+        let constraints = vec![(TVar(0), vec![bound("Copy")])];
+        let subst = [(TVar(0), ty("grid::Cell<u8>"))].into_iter().collect();
+        assert!(env.check(&constraints, &subst).is_ok());
+        let subst = [(TVar(0), ty("Vec<u8>"))].into_iter().collect();
+        assert!(matches!(
+            env.check(&constraints, &subst),
+            Err(Error::Unsatisfied { .. })
+        ));
+    }
+}
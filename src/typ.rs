@@ -1,24 +1,104 @@
 use ena::unify::UnifyValue;
 use quote::ToTokens;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
 use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
 use syn::{FieldsNamed, Path, PathSegment, Type, TypeArray};
 
+use crate::filesystem::FileSystem;
+
 /// Mapping of function names to type signatures
 pub type TypeMap = HashMap<crate::location::Loc, RustTypeSignature>;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Error {
-    UnUnifiableTypes(RustType, RustType),
+    /// `left` and `right` could not be unified, `path` lists the child indices (see
+    /// `RustType::children`) leading from the unified types to the mismatching sub-terms
+    Mismatch {
+        path: Vec<usize>,
+        left: Box<RustType>,
+        right: Box<RustType>,
+    },
+    /// binding `var` to `ty` at `path` would produce an infinite type
+    Occurs {
+        path: Vec<usize>,
+        var: TVar,
+        ty: Box<RustType>,
+    },
+    /// `ty` has no statically known layout, see `RustType::layout`
+    NoLayout {
+        ty: Box<RustType>,
+        reason: &'static str,
+    },
+    /// `ty` does not implement `bound`, see `traits::TraitEnv`
+    Unsatisfied {
+        ty: Box<RustType>,
+        bound: Box<RustTypeConstraint>,
+    },
+    /// A syntactic construct with no representation in the type model
+    Unsupported {
+        /// what kind of construct was rejected, e.g "type" or "generic argument"
+        construct: &'static str,
+        /// the offending tokens
+        tokens: String,
+        span: proc_macro2::Span,
+    },
+}
+
+impl Error {
+    pub fn unsupported<T: ToTokens>(construct: &'static str, node: &T) -> Self {
+        Error::Unsupported {
+            construct,
+            tokens: node.to_token_stream().to_string(),
+            span: node.span(),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Mismatch { path, left, right } => {
+                write!(f, "could not unify {} with {} at {:?}", left, right, path)
+            }
+            Error::Occurs { path, var, ty } => {
+                write!(
+                    f,
+                    "{} occurs in {} at {:?}, giving an infinite type",
+                    var, ty, path
+                )
+            }
+            Error::NoLayout { ty, reason } => write!(f, "{} has no known layout: {}", ty, reason),
+            Error::Unsatisfied { ty, bound } => write!(f, "{} does not implement {}", ty, bound),
+            Error::Unsupported {
+                construct,
+                tokens,
+                span,
+            } => {
+                let start = span.start();
+                write!(
+                    f,
+                    "found use of unsupported {} {:?} at {}:{}",
+                    construct, tokens, start.line, start.column
+                )
+            }
+        }
+    }
 }
 
+/// Type aliases, structs, enums and unions declared in a program, keyed by their path from the
+/// crate root, along with the imports of every module (the crate root being the empty path)
 pub type ProgramTypeContext = (
-    HashMap<syn::Ident, RustType>,
-    HashMap<syn::Ident, RustStruct>,
+    HashMap<RustPath, RustType>,
+    HashMap<RustPath, RustStruct>,
+    HashMap<RustPath, RustEnum>,
+    HashMap<RustPath, RustUnion>,
+    HashMap<RustPath, RustScope>,
 );
 
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct TVar(pub usize);
 
 impl From<&str> for TVar {
@@ -46,7 +126,35 @@ impl std::fmt::Display for TVar {
     }
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+/// A named lifetime, stored without its leading `'` (so `'static` is `static`)
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct RustLifetime(pub String);
+
+impl RustLifetime {
+    pub fn is_static(&self) -> bool {
+        self.0 == "static"
+    }
+}
+
+impl std::fmt::Display for RustLifetime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}", self.0)
+    }
+}
+
+impl From<syn::Lifetime> for RustLifetime {
+    fn from(lifetime: syn::Lifetime) -> Self {
+        RustLifetime(lifetime.ident.to_string())
+    }
+}
+
+impl From<RustLifetime> for syn::Lifetime {
+    fn from(lifetime: RustLifetime) -> Self {
+        syn::Lifetime::new(&format!("{}", lifetime), proc_macro2::Span::call_site())
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RustMutability {
     Immutable,
     Mutable,
@@ -79,7 +187,7 @@ impl Into<Option<syn::token::Mut>> for RustMutability {
     }
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CIntegralSize {
     Char,
     Short,
@@ -100,7 +208,7 @@ impl std::fmt::Display for CIntegralSize {
     }
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CFloatSize {
     Float,
     Double,
@@ -115,7 +223,119 @@ impl std::fmt::Display for CFloatSize {
     }
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+/// The calling convention of a function pointer
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RustAbi {
+    /// no `extern` qualifier
+    Rust,
+    /// `extern "name"`, or a bare `extern` when no name is given
+    Extern(Option<String>),
+}
+
+impl std::fmt::Display for RustAbi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RustAbi::Rust => write!(f, "rust"),
+            RustAbi::Extern(None) => write!(f, "extern"),
+            RustAbi::Extern(Some(name)) => write!(f, "extern({:?})", name),
+        }
+    }
+}
+
+impl From<Option<syn::Abi>> for RustAbi {
+    fn from(abi: Option<syn::Abi>) -> Self {
+        match abi {
+            None => RustAbi::Rust,
+            Some(syn::Abi { name, .. }) => RustAbi::Extern(name.map(|v| v.value())),
+        }
+    }
+}
+
+impl From<RustAbi> for Option<syn::Abi> {
+    fn from(abi: RustAbi) -> Self {
+        match abi {
+            RustAbi::Rust => None,
+            RustAbi::Extern(name) => Some(syn::Abi {
+                extern_token: Default::default(),
+                name: name.map(|v| syn::LitStr::new(&v, proc_macro2::Span::call_site())),
+            }),
+        }
+    }
+}
+
+/// A module-qualified path to a nominal type, e.g. `std::collections::HashMap`
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RustPath(pub Vec<syn::Ident>);
+
+impl RustPath {
+    pub fn segments(&self) -> &Vec<syn::Ident> {
+        &self.0
+    }
+
+    /// Returns the final segment of the path, i.e the name of the type
+    pub fn name(&self) -> &syn::Ident {
+        self.0.last().expect("empty path")
+    }
+
+    /// Returns the path without its final segment, i.e the module of the type
+    pub fn parent(&self) -> RustPath {
+        RustPath(self.0[..self.0.len().saturating_sub(1)].to_vec())
+    }
+
+    pub fn join(&self, segments: &[syn::Ident]) -> RustPath {
+        RustPath(self.0.iter().chain(segments).cloned().collect())
+    }
+}
+
+impl std::fmt::Display for RustPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let segments = self
+            .0
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join("::");
+        write!(f, "{}", segments)
+    }
+}
+
+impl From<&syn::Path> for RustPath {
+    fn from(path: &syn::Path) -> Self {
+        RustPath(path.segments.iter().map(|v| v.ident.clone()).collect())
+    }
+}
+
+impl From<syn::Ident> for RustPath {
+    fn from(ident: syn::Ident) -> Self {
+        RustPath(vec![ident])
+    }
+}
+
+impl From<RustPath> for syn::Path {
+    fn from(path: RustPath) -> Self {
+        syn::Path {
+            leading_colon: None,
+            segments: path.0.into_iter().map(PathSegment::from).collect(),
+        }
+    }
+}
+
+/// Returns true if the path refers to a type from libc or the std/core C FFI modules
+fn is_c_type_path(path: &Path) -> bool {
+    let segments = path
+        .segments
+        .iter()
+        .map(|v| v.ident.to_string())
+        .collect::<Vec<_>>();
+    match &segments[..] {
+        [libc, _] if libc == "libc" => true,
+        [std, os, raw, _] if std == "std" && os == "os" && raw == "raw" => true,
+        [krate, ffi, _] if (krate == "std" || krate == "core") && ffi == "ffi" => true,
+        _ => false,
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RustType {
     CVoid,
     CInt {
@@ -123,69 +343,264 @@ pub enum RustType {
         size: CIntegralSize,
     },
     CFloat(CFloatSize),
-    CAlias(syn::Ident),
+    CAlias(#[serde(with = "crate::serialize::ident")] syn::Ident),
 
     Array(Box<RustType>, usize),
+    /// [T]
+    Slice(Box<RustType>),
+    /// (T1, T2, ...), the empty tuple is represented by `Unit`
+    Tuple(Vec<RustType>),
 
     Option(Box<RustType>),
     Vec(Box<RustType>),
+    /// Nominal type applied to lifetime then type arguments, e.g `Box<T>` or `Cow<'a, str>`
+    Adt(RustPath, Vec<RustLifetime>, Vec<RustType>),
     Unit,
+    Bool,
+    Char,
+    /// unsized string slice, usually seen behind a reference (`&str`)
+    Str,
+    I8,
+    I16,
     I32,
-    U8,
-    SizeT,
+    I64,
+    I128,
     Isize,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
     Usize,
+    F32,
+    F64,
+    SizeT,
     TVar(TVar), //rust types
 
     Never,
 
-    ExternFn(Vec<Box<RustType>>, bool, Box<RustType>),
+    /// fn pointer, e.g `unsafe extern "C" fn(c_int, ...) -> c_int`
+    FnPtr {
+        unsafety: bool,
+        abi: RustAbi,
+        args: Vec<RustType>,
+        variadic: bool,
+        output: Box<RustType>,
+    },
+
+    /// &'a T or &'a mut T, with an optional lifetime
+    Reference(Option<RustLifetime>, RustMutability, Box<RustType>),
+    /// *mut T or *const T
+    Pointer(RustMutability, Box<RustType>),
 
-    /// immutable reference
-    Reference(RustMutability, Box<RustType>),
-    /// *mut T
-    Pointer(Box<RustType>),
+    /// dyn B1 + B2 + ...
+    TraitObject(Vec<RustTypeConstraint>),
+    /// impl B1 + B2 + ...
+    Opaque(Vec<RustTypeConstraint>),
 }
 
 impl RustType {
-    fn uses(&self, set: &mut HashSet<syn::Ident>) {
+    /// Returns true if the type is one of Rust's built-in primitive types
+    pub fn is_primitive(&self) -> bool {
+        matches!(
+            self,
+            RustType::Unit
+                | RustType::Bool
+                | RustType::Char
+                | RustType::Str
+                | RustType::I8
+                | RustType::I16
+                | RustType::I32
+                | RustType::I64
+                | RustType::I128
+                | RustType::Isize
+                | RustType::U8
+                | RustType::U16
+                | RustType::U32
+                | RustType::U64
+                | RustType::U128
+                | RustType::Usize
+                | RustType::F32
+                | RustType::F64
+                | RustType::Never
+        )
+    }
+
+    /// Returns the immediate sub-terms of the type, in a fixed order: the element type of
+    /// wrappers, arguments of tuples and nominal types, arguments then output of fn pointers, and
+    /// the types mentioned by each bound of trait objects and opaque types
+    pub fn children(&self) -> Vec<&RustType> {
+        match self {
+            RustType::Option(ty)
+            | RustType::Vec(ty)
+            | RustType::Array(ty, _)
+            | RustType::Slice(ty)
+            | RustType::Reference(_, _, ty)
+            | RustType::Pointer(_, ty) => vec![ty],
+            RustType::Tuple(elts) | RustType::Adt(_, _, elts) => elts.iter().collect(),
+            RustType::FnPtr { args, output, .. } => {
+                args.iter().chain(std::iter::once(&**output)).collect()
+            }
+            RustType::TraitObject(bounds) | RustType::Opaque(bounds) => {
+                bounds.iter().flat_map(|bound| bound.types()).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Mutable version of `children`, in the same order
+    pub fn children_mut(&mut self) -> Vec<&mut RustType> {
+        match self {
+            RustType::Option(ty)
+            | RustType::Vec(ty)
+            | RustType::Array(ty, _)
+            | RustType::Slice(ty)
+            | RustType::Reference(_, _, ty)
+            | RustType::Pointer(_, ty) => vec![ty],
+            RustType::Tuple(elts) | RustType::Adt(_, _, elts) => elts.iter_mut().collect(),
+            RustType::FnPtr { args, output, .. } => args
+                .iter_mut()
+                .chain(std::iter::once(&mut **output))
+                .collect(),
+            RustType::TraitObject(bounds) | RustType::Opaque(bounds) => bounds
+                .iter_mut()
+                .flat_map(|bound| bound.types_mut())
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Returns the type variables occurring in the type, in order of first occurrence
+    pub fn free_vars(&self) -> Vec<TVar> {
+        let mut vars = vec![];
+        self.collect_vars(&mut vars);
+        vars
+    }
+
+    fn collect_vars(&self, vars: &mut Vec<TVar>) {
+        match self {
+            RustType::TVar(tvar) if !vars.contains(tvar) => vars.push(*tvar),
+            ty => {
+                for child in ty.children() {
+                    child.collect_vars(vars)
+                }
+            }
+        }
+    }
+
+    /// Replaces every occurrence of `target` in the type by the type variable `tvar`
+    pub fn abstract_over(&self, target: &RustType, tvar: TVar) -> RustType {
+        if self == target {
+            return RustType::TVar(tvar);
+        }
+        let mut ty = self.clone();
+        for child in ty.children_mut() {
+            *child = child.abstract_over(target, tvar)
+        }
+        ty
+    }
+
+    /// Builds a nominal type, using `CAlias` for paths with a single segment and no arguments
+    pub fn nominal(path: RustPath, args: Vec<RustType>) -> Self {
+        match path.segments().as_slice() {
+            [name] if args.is_empty() => RustType::CAlias(name.clone()),
+            _ => RustType::Adt(path, vec![], args),
+        }
+    }
+
+    /// Qualifies every nominal type in a type written in `module`, see `qualify_path`
+    pub fn qualify(&mut self, module: &RustPath, ctxt: &ProgramTypeContext) {
+        match self {
+            RustType::CAlias(id) => {
+                let path = qualify_path(&id.clone().into(), module, ctxt);
+                *self = RustType::nominal(path, vec![])
+            }
+            RustType::Adt(path, lifetimes, args) => {
+                for arg in args.iter_mut() {
+                    arg.qualify(module, ctxt)
+                }
+                *path = qualify_path(path, module, ctxt);
+                if lifetimes.is_empty() {
+                    *self = RustType::nominal(path.clone(), std::mem::take(args))
+                }
+            }
+            ty => {
+                for child in ty.children_mut() {
+                    child.qualify(module, ctxt)
+                }
+            }
+        }
+    }
+
+    /// Returns the path of the declaration a nominal type refers to, if it should be looked up
+    /// in the type context: every `CAlias`, and `Adt`s that name a type alias
+    fn alias_key(&self, ctxt: &ProgramTypeContext) -> Option<RustPath> {
+        match self {
+            RustType::CAlias(id) => Some(id.clone().into()),
+            RustType::Adt(path, _, args) if args.is_empty() && ctxt.0.contains_key(path) => {
+                Some(path.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn uses(&self, set: &mut HashSet<RustPath>) {
         match self {
             RustType::CAlias(id) => {
-                set.insert(id.clone());
+                set.insert(id.clone().into());
                 ()
             }
             RustType::Option(ty)
             | RustType::Vec(ty)
-            | RustType::Reference(_, ty)
-            | RustType::Pointer(ty)
-            | RustType::Array(ty, _) => ty.uses(set),
+            | RustType::Reference(_, _, ty)
+            | RustType::Pointer(_, ty)
+            | RustType::Array(ty, _)
+            | RustType::Slice(ty) => ty.uses(set),
+            RustType::Tuple(elts) => {
+                for elt in elts.iter() {
+                    elt.uses(set)
+                }
+            }
+            RustType::Adt(path, _, args) => {
+                set.insert(path.clone());
+                for arg in args.iter() {
+                    arg.uses(set)
+                }
+            }
+            RustType::TraitObject(bounds) | RustType::Opaque(bounds) => {
+                for ty in bounds.iter().flat_map(|bound| bound.types()) {
+                    ty.uses(set)
+                }
+            }
 
-            // RustType::ExternFn(args, _, out_ty) => {
+            // RustType::FnPtr { args, output, .. } => {
             //     for arg in args.iter() {
             //         arg.uses(set)
             //     }
-            //     out_ty.uses(set)
+            //     output.uses(set)
             // }
             _ => (),
         }
     }
 
-    fn resolve_checked(
-        &mut self,
-        path: &mut HashSet<syn::Ident>,
-        ctxt: &ProgramTypeContext,
-    ) -> bool {
+    fn resolve_checked(&mut self, path: &mut HashSet<RustPath>, ctxt: &ProgramTypeContext) -> bool {
         match self {
             // recursion check
-            RustType::CAlias(id) if path.contains(id) => true,
-            RustType::CAlias(id) if !path.contains(id) => {
+            RustType::CAlias(_) | RustType::Adt(..)
+                if self.alias_key(ctxt).is_some_and(|key| path.contains(&key)) =>
+            {
+                true
+            }
+            RustType::CAlias(_) | RustType::Adt(..) if self.alias_key(ctxt).is_some() => {
+                let id = self.alias_key(ctxt).unwrap();
                 // add the visited alias to the path
                 path.insert(id.clone());
-                // if type alias to a defined struct, then we good boys
-                if ctxt.1.contains_key(id) {
+                // if type alias to a defined struct, enum or union, then we good boys
+                if ctxt.1.contains_key(&id) || ctxt.2.contains_key(&id) || ctxt.3.contains_key(&id)
+                {
                     false
                 } else {
-                    match ctxt.0.get(id) {
+                    match ctxt.0.get(&id) {
                         Some(inner) => {
                             // update self to be the inner type
                             *self = inner.clone();
@@ -201,10 +616,13 @@ impl RustType {
             }
             RustType::Option(elt)
             | RustType::Vec(elt)
-            | RustType::Pointer(elt)
-            | RustType::Reference(_, elt)
-            | RustType::Array(elt, _) => elt.resolve_checked(path, ctxt),
-            RustType::ExternFn(args, _, out) => {
+            | RustType::Pointer(_, elt)
+            | RustType::Reference(_, _, elt)
+            | RustType::Array(elt, _)
+            | RustType::Slice(elt) => elt.resolve_checked(path, ctxt),
+            RustType::FnPtr {
+                args, output: out, ..
+            } => {
                 let mut any_rec = false;
                 let mut base_path = path.clone();
                 for arg in args.iter_mut() {
@@ -216,49 +634,189 @@ impl RustType {
                 path.extend(base_path.into_iter());
                 any_rec
             }
+            RustType::Adt(_, _, args) | RustType::Tuple(args) => {
+                let mut any_rec = false;
+                let base_path = path.clone();
+                for arg in args.iter_mut() {
+                    let mut rec_path = base_path.clone();
+                    any_rec |= arg.resolve_checked(&mut rec_path, ctxt);
+                    path.extend(rec_path);
+                }
+                any_rec
+            }
+            RustType::TraitObject(bounds) | RustType::Opaque(bounds) => {
+                let mut any_rec = false;
+                let base_path = path.clone();
+                for ty in bounds.iter_mut().flat_map(|bound| bound.types_mut()) {
+                    let mut rec_path = base_path.clone();
+                    any_rec |= ty.resolve_checked(&mut rec_path, ctxt);
+                    path.extend(rec_path);
+                }
+                any_rec
+            }
             _ => false,
         }
     }
 
     /// Resolves a type according to the type context, avoiding loops, returning the list of types visited
-    pub fn resolve(&mut self, ctxt: &ProgramTypeContext) -> HashSet<syn::Ident> {
+    pub fn resolve(&mut self, ctxt: &ProgramTypeContext) -> HashSet<RustPath> {
         let mut set = HashSet::new();
         self.resolve_checked(&mut set, ctxt);
         set
     }
 }
 
+/// Words printed by `RustType`'s `Display` for types other than aliases
+const RESERVED_NAMES: &[&str] = &[
+    "never",
+    "c_void",
+    "c_float",
+    "c_double",
+    "c_char",
+    "c_uchar",
+    "c_short",
+    "c_ushort",
+    "c_int",
+    "c_uint",
+    "c_long",
+    "c_ulong",
+    "c_longlong",
+    "c_ulonglong",
+    "size_t",
+    "bool",
+    "char",
+    "str",
+    "i8",
+    "i16",
+    "i32",
+    "i64",
+    "i128",
+    "isize",
+    "u8",
+    "u16",
+    "u32",
+    "u64",
+    "u128",
+    "usize",
+    "f32",
+    "f64",
+];
+
+/// Prefixes of the printed form of pointers and references
+const RESERVED_PREFIXES: &[&str] = &["mut_ptr_", "const_ptr_", "ref_"];
+
+/// Whether an alias named `name` must be printed as `alias(name)` to be read back as an alias
+fn is_reserved_name(name: &str) -> bool {
+    let is_tvar = name
+        .strip_prefix('T')
+        .is_some_and(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()));
+    is_tvar
+        || RESERVED_NAMES.contains(&name)
+        || RESERVED_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
 impl std::fmt::Display for RustType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             RustType::Never => write!(f, "never"),
             RustType::Array(box ty, size) => write!(f, "array({}, {})", ty, size),
+            RustType::Slice(box ty) => write!(f, "slice({})", ty),
+            RustType::TraitObject(bounds) => write!(
+                f,
+                "dyn({})",
+                bounds
+                    .iter()
+                    .map(|v| format!("{}", v))
+                    .collect::<Vec<_>>()
+                    .join(" + ")
+            ),
+            RustType::Opaque(bounds) => write!(
+                f,
+                "impl({})",
+                bounds
+                    .iter()
+                    .map(|v| format!("{}", v))
+                    .collect::<Vec<_>>()
+                    .join(" + ")
+            ),
+            RustType::Tuple(elts) => write!(
+                f,
+                "tuple({})",
+                elts.iter()
+                    .map(|v| format!("{}", v))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
             RustType::Option(box ty) => write!(f, "option({})", ty),
             RustType::Vec(box ty) => write!(f, "vec({})", ty),
+            RustType::Adt(path, lifetimes, args) => write!(
+                f,
+                "adt({}, [{}])",
+                path,
+                lifetimes
+                    .iter()
+                    .map(|lt| format!("{}", lt))
+                    .chain(args.iter().map(|v| format!("{}", v)))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
             RustType::CInt { unsigned, size } => {
                 write!(f, "c_{}{}", if *unsigned { "u" } else { "" }, size)
             }
             RustType::CFloat(size) => write!(f, "c_{}", size),
             RustType::CVoid => write!(f, "c_void"),
-            RustType::CAlias(ident) => write!(f, "{}", ident),
+            RustType::CAlias(ident) => {
+                // names that would be read back as another type, e.g `T0` or `c_int`, are escaped
+                let name = ident.to_string();
+                if is_reserved_name(&name) {
+                    write!(f, "alias({})", name)
+                } else {
+                    write!(f, "{}", name)
+                }
+            }
             RustType::Unit => write!(f, "()"),
             RustType::SizeT => write!(f, "size_t"),
-            RustType::U8 => write!(f, "u8"),
+            RustType::Bool => write!(f, "bool"),
+            RustType::Char => write!(f, "char"),
+            RustType::Str => write!(f, "str"),
+            RustType::I8 => write!(f, "i8"),
+            RustType::I16 => write!(f, "i16"),
             RustType::I32 => write!(f, "i32"),
+            RustType::I64 => write!(f, "i64"),
+            RustType::I128 => write!(f, "i128"),
             RustType::Isize => write!(f, "isize"),
+            RustType::U8 => write!(f, "u8"),
+            RustType::U16 => write!(f, "u16"),
+            RustType::U32 => write!(f, "u32"),
+            RustType::U64 => write!(f, "u64"),
+            RustType::U128 => write!(f, "u128"),
             RustType::Usize => write!(f, "usize"),
+            RustType::F32 => write!(f, "f32"),
+            RustType::F64 => write!(f, "f64"),
             RustType::TVar(tvar) => write!(f, "{}", tvar),
-            RustType::Pointer(box x) => write!(f, "mut_ptr_{}", x),
-            RustType::Reference(mt, box x) => write!(f, "ref_{}_{}", mt, x),
-            RustType::ExternFn(args, variadic, body) => write!(
-                f,
-                "extern_fn_({}, {}, {})",
+            RustType::Pointer(RustMutability::Mutable, box x) => write!(f, "mut_ptr_{}", x),
+            RustType::Pointer(RustMutability::Immutable, box x) => write!(f, "const_ptr_{}", x),
+            RustType::Reference(None, mt, box x) => write!(f, "ref_{}_{}", mt, x),
+            RustType::Reference(Some(lt), mt, box x) => write!(f, "ref<{}>_{}_{}", lt, mt, x),
+            RustType::FnPtr {
+                unsafety,
+                abi,
+                args,
                 variadic,
+                output,
+            } => write!(
+                f,
+                "fn_ptr({}, {}, [{}], {}, {})",
+                if *unsafety { "unsafe" } else { "safe" },
+                abi,
                 args.iter()
                     .map(|v| format!("{}", v))
                     .collect::<Vec<_>>()
-                    .join(","),
-                body
+                    .join(", "),
+                if *variadic { "variadic" } else { "fixed" },
+                output
             ),
         }
     }
@@ -276,6 +834,36 @@ impl Into<Type> for RustType {
                 semi_token: Default::default(),
                 len: syn::parse_str::<syn::Expr>(&format!("{}", size)).unwrap(),
             }),
+            RustType::TraitObject(bounds) => Type::TraitObject(syn::TypeTraitObject {
+                dyn_token: Some(Default::default()),
+                bounds: bounds
+                    .into_iter()
+                    .map(|v| -> syn::TypeParamBound { v.into() })
+                    .collect(),
+            }),
+            RustType::Opaque(bounds) => Type::ImplTrait(syn::TypeImplTrait {
+                impl_token: Default::default(),
+                bounds: bounds
+                    .into_iter()
+                    .map(|v| -> syn::TypeParamBound { v.into() })
+                    .collect(),
+            }),
+            RustType::Slice(box ty) => Type::Slice(syn::TypeSlice {
+                bracket_token: Default::default(),
+                elem: Box::new(ty.into()),
+            }),
+            RustType::Tuple(elts) => {
+                let mut elems: Punctuated<Type, syn::token::Comma> =
+                    elts.into_iter().map(|ty| -> Type { ty.into() }).collect();
+                // a one element tuple needs its trailing comma to not be a parenthesised type
+                if elems.len() == 1 {
+                    elems.push_punct(Default::default());
+                }
+                Type::Tuple(syn::TypeTuple {
+                    paren_token: Default::default(),
+                    elems,
+                })
+            }
             RustType::Option(box ty) => Type::Path(syn::TypePath {
                 qself: None,
                 path: syn::Path {
@@ -318,6 +906,26 @@ impl Into<Type> for RustType {
                     .collect(),
                 },
             }),
+            RustType::Adt(path, lifetimes, args) => {
+                let mut path: syn::Path = path.into();
+                if !lifetimes.is_empty() || !args.is_empty() {
+                    path.segments.last_mut().unwrap().arguments =
+                        syn::PathArguments::AngleBracketed(syn::AngleBracketedGenericArguments {
+                            colon2_token: None,
+                            lt_token: Default::default(),
+                            args: lifetimes
+                                .into_iter()
+                                .map(|lt| syn::GenericArgument::Lifetime(lt.into()))
+                                .chain(
+                                    args.into_iter()
+                                        .map(|ty| syn::GenericArgument::Type(ty.into())),
+                                )
+                                .collect(),
+                            gt_token: Default::default(),
+                        })
+                }
+                Type::Path(syn::TypePath { qself: None, path })
+            }
             RustType::Unit => syn::parse_str::<Type>("()").unwrap(),
             ty @ RustType::CInt { .. } => syn::parse_str::<Type>(&format!("libc::{}", ty)).unwrap(),
             ty @ RustType::CFloat(_) => syn::parse_str::<Type>(&format!("libc::{}", ty)).unwrap(),
@@ -331,136 +939,193 @@ impl Into<Type> for RustType {
                 },
             }),
             RustType::SizeT => syn::parse_str::<Type>("size_t").unwrap(),
-            RustType::U8 => syn::parse_str::<Type>("u8").unwrap(),
-            RustType::I32 => syn::parse_str::<Type>("i32").unwrap(),
-            RustType::Isize => syn::parse_str::<Type>("isize").unwrap(),
-            RustType::Usize => syn::parse_str::<Type>("usize").unwrap(),
+            ty @ (RustType::Bool
+            | RustType::Char
+            | RustType::Str
+            | RustType::I8
+            | RustType::I16
+            | RustType::I32
+            | RustType::I64
+            | RustType::I128
+            | RustType::Isize
+            | RustType::U8
+            | RustType::U16
+            | RustType::U32
+            | RustType::U64
+            | RustType::U128
+            | RustType::Usize
+            | RustType::F32
+            | RustType::F64) => syn::parse_str::<Type>(&format!("{}", ty)).unwrap(),
             RustType::TVar(n) => syn::parse_str::<Type>(&format!("{}", n)).unwrap(),
-            RustType::Pointer(box v) => Type::Ptr(syn::TypePtr {
-                const_token: None,
-                mutability: Some(Default::default()),
+            RustType::Pointer(muta, box v) => Type::Ptr(syn::TypePtr {
+                const_token: match muta {
+                    RustMutability::Immutable => Some(Default::default()),
+                    RustMutability::Mutable => None,
+                },
+                mutability: muta.into(),
                 elem: Box::new(v.into()),
                 star_token: Default::default(),
             }),
-            RustType::Reference(muta, box v) => Type::Reference(syn::TypeReference {
+            RustType::Reference(lifetime, muta, box v) => Type::Reference(syn::TypeReference {
                 and_token: Default::default(),
                 mutability: muta.into(),
                 elem: Box::new(v.into()),
-                lifetime: None,
+                lifetime: lifetime.map(|lt| lt.into()),
             }),
-            RustType::ExternFn(args, variadic, box res) => {
-                let inputs = if variadic {
-                    args.into_iter()
-                        .map(|f| syn::BareFnArg {
-                            attrs: Default::default(),
-                            name: None,
-                            ty: (*f).into(),
-                        })
-                        .collect()
+            RustType::FnPtr {
+                unsafety,
+                abi,
+                args,
+                variadic,
+                output: box output,
+            } => Type::BareFn(syn::TypeBareFn {
+                lifetimes: None,
+                unsafety: if unsafety {
+                    Some(Default::default())
                 } else {
-                    args.into_iter()
-                        .map(|f| syn::BareFnArg {
-                            attrs: Default::default(),
-                            name: None,
-                            ty: (*f).into(),
-                        })
-                        .chain(
-                            [syn::BareFnArg {
-                                attrs: Default::default(),
-                                name: None,
-                                ty: Type::Verbatim("...".to_token_stream()),
-                            }]
-                            .into_iter(),
-                        )
-                        .collect()
-                };
-
-                Type::BareFn(syn::TypeBareFn {
-                    lifetimes: None,
-                    unsafety: Some(Default::default()),
-                    abi: Some(syn::Abi {
-                        extern_token: Default::default(),
-                        name: Some(syn::parse_str::<syn::LitStr>("\"C\"").unwrap()),
-                    }),
-                    fn_token: Default::default(),
-                    paren_token: Default::default(),
-                    inputs,
-                    variadic: None,
-                    output: syn::ReturnType::Type(Default::default(), Box::new(res.into())),
-                })
-            }
+                    None
+                },
+                abi: abi.into(),
+                fn_token: Default::default(),
+                paren_token: Default::default(),
+                inputs: args
+                    .into_iter()
+                    .map(|ty| syn::BareFnArg {
+                        attrs: Default::default(),
+                        name: None,
+                        ty: ty.into(),
+                    })
+                    .collect(),
+                variadic: if variadic {
+                    Some(syn::Variadic {
+                        attrs: Default::default(),
+                        dots: Default::default(),
+                    })
+                } else {
+                    None
+                },
+                output: match output {
+                    RustType::Unit => syn::ReturnType::Default,
+                    output => syn::ReturnType::Type(Default::default(), Box::new(output.into())),
+                },
+            }),
         }
     }
 }
 
+/// Returns the single type argument of a path such as `Option<T>`
+fn single_type_argument(path: &Path) -> Result<&Type, Error> {
+    match &path.segments.last().unwrap().arguments {
+        syn::PathArguments::AngleBracketed(syn::AngleBracketedGenericArguments {
+            args, ..
+        }) => match args.first() {
+            Some(syn::GenericArgument::Type(ty)) if args.len() == 1 => Ok(ty),
+            _ => Err(Error::unsupported("generic argument", path)),
+        },
+        _ => Err(Error::unsupported("generic argument", path)),
+    }
+}
+
+/// Panics on types that have no `RustType` representation, use `RustType::try_from` to handle
+/// them gracefully
 impl From<Type> for RustType {
     fn from(ty: Type) -> Self {
-        match ty {
+        RustType::try_from(&ty).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+impl TryFrom<&Type> for RustType {
+    type Error = Error;
+
+    fn try_from(ty: &Type) -> Result<Self, Self::Error> {
+        let ty = match ty {
+            // associated types such as `<T as Iterator>::Item`
+            Type::Path(syn::TypePath { qself: Some(_), .. }) => {
+                return Err(Error::unsupported("type", ty))
+            }
             Type::Path(syn::TypePath {
-                path: Path { segments, .. },
-                ..
+                path: path @ Path { segments, .. },
+                qself: None,
             }) if segments.last().is_some_and(|segment| {
                 segment.ident == "Option" && !segment.arguments.is_empty()
             }) =>
             {
-                let syn::PathArguments::AngleBracketed(syn::AngleBracketedGenericArguments { args, .. }) =
-                        &segments.last().unwrap().arguments else {
-                        panic!("found use of unsupported syntactic construct {} in code", segments.to_token_stream().to_string())
-                    };
-
-                let syn::GenericArgument::Type(ty) = &args[0] else {
-                        panic!("found use of unsupported syntactic construct {} in code", segments.to_token_stream().to_string())
-                    };
-
-                RustType::Option(Box::new(ty.clone().into()))
+                let ty = single_type_argument(path)?;
+                RustType::Option(Box::new(ty.try_into()?))
             }
             Type::Path(syn::TypePath {
-                path: Path { segments, .. },
-                ..
+                path: path @ Path { segments, .. },
+                qself: None,
             }) if segments
                 .last()
                 .is_some_and(|segment| segment.ident == "Vec" && !segment.arguments.is_empty()) =>
             {
-                let syn::PathArguments::AngleBracketed(syn::AngleBracketedGenericArguments { args, .. }) =
-                        &segments.last().unwrap().arguments else {
-                        panic!("found use of unsupported syntactic construct {} in code", segments.to_token_stream().to_string())
-                    };
-
-                let syn::GenericArgument::Type(ty) = &args[0] else {
-                        panic!("found use of unsupported syntactic construct {} in code", segments.to_token_stream().to_string())
-                    };
-
-                RustType::Vec(Box::new(ty.clone().into()))
+                let ty = single_type_argument(path)?;
+                RustType::Vec(Box::new(ty.try_into()?))
             }
+            // generic or module-qualified nominal types
             Type::Path(syn::TypePath {
-                path: ref path @ Path { ref segments, .. },
-                ..
+                path: path @ Path { segments, .. },
+                qself: None,
             }) if segments
                 .last()
-                .is_some_and(|segment| segment.arguments.is_empty()) =>
+                .is_some_and(|segment| !segment.arguments.is_empty())
+                || (segments.len() > 1 && !is_c_type_path(path)) =>
             {
-                let ident = &segments.last().unwrap().ident;
-
-                if !(segments.len() == 1)
-                    && !(segments.len() > 1 && segments[0].ident == "libc")
-                    && !(segments.len() > 3
-                        && segments[0].ident == "std"
-                        && segments[1].ident == "os"
-                        && segments[2].ident == "raw")
-                {
-                    log::warn!("found use of non libc type {:?}, optimistically assuming nothing crazy is going on",
-                               path.to_token_stream().to_string()
-                    )
-                }
-
+                let mut lifetimes = vec![];
+                let mut args = vec![];
+                match &segments.last().unwrap().arguments {
+                    syn::PathArguments::None => (),
+                    syn::PathArguments::AngleBracketed(syn::AngleBracketedGenericArguments {
+                        args: generic_args,
+                        ..
+                    }) => {
+                        for arg in generic_args.iter() {
+                            match arg {
+                                syn::GenericArgument::Lifetime(lt) => {
+                                    lifetimes.push(lt.clone().into())
+                                }
+                                syn::GenericArgument::Type(ty) => args.push(ty.try_into()?),
+                                arg => return Err(Error::unsupported("generic argument", arg)),
+                            }
+                        }
+                    }
+                    syn::PathArguments::Parenthesized(args) => {
+                        return Err(Error::unsupported("parenthesized type arguments", args))
+                    }
+                };
+                RustType::Adt(path.into(), lifetimes, args)
+            }
+            Type::Path(syn::TypePath {
+                path: Path { segments, .. },
+                qself: None,
+            }) if segments
+                .last()
+                .is_some_and(|segment| segment.arguments.is_empty()) =>
+            {
+                let ident = &segments.last().unwrap().ident;
+
                 use RustType::*;
 
                 match ident.to_string().as_str() {
-                    "isize" => Isize,
+                    "bool" => Bool,
+                    "char" => Char,
+                    "str" => Str,
+                    "i8" => I8,
+                    "i16" => I16,
                     "i32" => I32,
-                    "size_t" => SizeT,
+                    "i64" => I64,
+                    "i128" => I128,
+                    "isize" => Isize,
                     "u8" => U8,
+                    "u16" => U16,
+                    "u32" => U32,
+                    "u64" => U64,
+                    "u128" => U128,
                     "usize" => Usize,
+                    "f32" => F32,
+                    "f64" => F64,
+                    "size_t" => SizeT,
                     "c_float" => CFloat(CFloatSize::Float),
                     "c_double" => CFloat(CFloatSize::Double),
 
@@ -505,11 +1170,11 @@ impl From<Type> for RustType {
 
                     "c_longlong" => CInt {
                         unsigned: false,
-                        size: CIntegralSize::Long,
+                        size: CIntegralSize::LongLong,
                     },
                     "c_ulonglong" => CInt {
                         unsigned: true,
-                        size: CIntegralSize::Long,
+                        size: CIntegralSize::LongLong,
                     },
 
                     "c_void" => CVoid,
@@ -517,89 +1182,451 @@ impl From<Type> for RustType {
                     _txt => RustType::CAlias(ident.clone()),
                 }
             }
+            // syn guarantees exactly one of `const` or `mut` is present
             Type::Ptr(syn::TypePtr {
-                const_token,
                 mutability,
                 elem: box ty,
                 ..
-            }) => {
-                match (const_token, mutability) {
-                    (None, Some(_)) => RustType::Pointer(Box::new(ty.into())), //case where we have *mut T
-                    (Some(_), None) => RustType::Pointer(Box::new(ty.into())), //case where we have *const T
-                    (_, _) => {
-                        todo!()
-                    }
-                }
-            }
+            }) => RustType::Pointer((*mutability).into(), Box::new(ty.try_into()?)),
             Type::Reference(syn::TypeReference {
-                lifetime: None,
+                lifetime,
                 mutability,
                 elem: box elem,
                 ..
-            }) => RustType::Reference(mutability.into(), Box::new(elem.into())),
+            }) => RustType::Reference(
+                lifetime.clone().map(|lt| lt.into()),
+                (*mutability).into(),
+                Box::new(elem.try_into()?),
+            ),
 
-            Type::Tuple(syn::TypeTuple { elems, .. }) if elems.len() == 0 => RustType::Unit,
+            Type::Tuple(syn::TypeTuple { elems, .. }) if elems.is_empty() => RustType::Unit,
+            Type::Tuple(syn::TypeTuple { elems, .. }) => RustType::Tuple(
+                elems
+                    .iter()
+                    .map(|ty| ty.try_into())
+                    .collect::<Result<_, _>>()?,
+            ),
+            Type::Slice(syn::TypeSlice { elem: box ty, .. }) => {
+                RustType::Slice(Box::new(ty.try_into()?))
+            }
+            Type::Paren(syn::TypeParen { elem: box ty, .. }) => ty.try_into()?,
+            Type::TraitObject(syn::TypeTraitObject { bounds, .. }) => RustType::TraitObject(
+                bounds
+                    .iter()
+                    .map(|v| v.try_into())
+                    .collect::<Result<_, _>>()?,
+            ),
+            Type::ImplTrait(syn::TypeImplTrait { bounds, .. }) => RustType::Opaque(
+                bounds
+                    .iter()
+                    .map(|v| v.try_into())
+                    .collect::<Result<_, _>>()?,
+            ),
 
+            // higher-ranked fn pointers such as `for<'a> fn(&'a u8)`
             Type::BareFn(syn::TypeBareFn {
-                unsafety: Some(_),
-                abi: Some(_),
+                lifetimes: Some(_), ..
+            }) => return Err(Error::unsupported("type", ty)),
+            Type::BareFn(syn::TypeBareFn {
+                lifetimes: None,
+                unsafety,
+                abi,
                 inputs,
                 output,
-                variadic: None,
+                variadic,
                 ..
             }) => {
-                let mut variadic = false;
-                let inputs = inputs
-                    .into_iter()
-                    .map(|v| match v.ty {
-                        Type::Verbatim(_v) => {
-                            variadic = true;
+                // older C2Rust output spells the variadic argument as a verbatim `...`
+                let mut is_variadic = variadic.is_some();
+                let args = inputs
+                    .iter()
+                    .filter_map(|v| match &v.ty {
+                        Type::Verbatim(tokens) if tokens.to_string() == "..." => {
+                            is_variadic = true;
                             None
                         }
-                        _ => Some(Box::new(v.ty.into())),
+                        ty => Some(ty.try_into()),
                     })
-                    .flatten()
-                    .collect();
+                    .collect::<Result<_, _>>()?;
 
                 let output = match output {
                     syn::ReturnType::Default => RustType::Unit,
-                    syn::ReturnType::Type(_, box ty) => ty.into(),
+                    syn::ReturnType::Type(_, box ty) => ty.try_into()?,
                 };
-                RustType::ExternFn(inputs, variadic, Box::new(output))
+                RustType::FnPtr {
+                    unsafety: unsafety.is_some(),
+                    abi: abi.clone().into(),
+                    args,
+                    variadic: is_variadic,
+                    output: Box::new(output),
+                }
             }
 
             Type::Array(TypeArray {
                 elem: box ty,
                 len:
-                    syn::Expr::Lit(syn::ExprLit {
+                    len @ syn::Expr::Lit(syn::ExprLit {
                         lit: syn::Lit::Int(i),
                         ..
                     }),
                 ..
-            }) => RustType::Array(Box::new(ty.into()), i.base10_parse().unwrap()),
+            }) => RustType::Array(
+                Box::new(ty.try_into()?),
+                i.base10_parse()
+                    .map_err(|_| Error::unsupported("array length", len))?,
+            ),
             Type::Never(_) => RustType::Never,
-            _ => panic!("unsupported type {:?}", ty.to_token_stream().to_string()),
+            ty => return Err(Error::unsupported("type", ty)),
+        };
+        Ok(ty)
+    }
+}
+
+/// Visibility of an item or a field
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RustVisibility {
+    Private,
+    Public,
+    /// `pub(crate)`
+    Crate,
+    /// `pub(super)` or `pub(in path)`
+    Restricted(RustPath),
+}
+
+impl From<&syn::Visibility> for RustVisibility {
+    fn from(vis: &syn::Visibility) -> Self {
+        match vis {
+            syn::Visibility::Inherited => RustVisibility::Private,
+            syn::Visibility::Public(_) => RustVisibility::Public,
+            syn::Visibility::Crate(_) => RustVisibility::Crate,
+            syn::Visibility::Restricted(syn::VisRestricted { path, .. }) => {
+                if path.is_ident("crate") {
+                    RustVisibility::Crate
+                } else if path.is_ident("self") {
+                    RustVisibility::Private
+                } else {
+                    RustVisibility::Restricted((&**path).into())
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for RustVisibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RustVisibility::Private => Ok(()),
+            RustVisibility::Public => write!(f, "pub "),
+            RustVisibility::Crate => write!(f, "pub(crate) "),
+            RustVisibility::Restricted(path) => write!(f, "pub(in {}) ", path),
+        }
+    }
+}
+
+/// Attributes of a type declaration that are relevant to its representation
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RustAttributes {
+    /// arguments of `#[repr(..)]`, e.g `C`, `packed` or `align(8)`
+    pub repr: Vec<String>,
+    /// traits listed in `#[derive(..)]`
+    pub derives: Vec<RustPath>,
+    /// any other attribute, kept verbatim (doc comments are dropped)
+    pub other: Vec<String>,
+}
+
+impl From<&[syn::Attribute]> for RustAttributes {
+    fn from(attrs: &[syn::Attribute]) -> Self {
+        let mut res = RustAttributes::default();
+        for attr in attrs.iter() {
+            match attr.parse_meta() {
+                Ok(syn::Meta::NameValue(meta)) if meta.path.is_ident("doc") => (),
+                Ok(syn::Meta::List(list)) if list.path.is_ident("repr") => res.repr.extend(
+                    list.nested
+                        .iter()
+                        .map(|v| v.to_token_stream().to_string().replace(' ', "")),
+                ),
+                Ok(syn::Meta::List(list)) if list.path.is_ident("derive") => {
+                    for nested in list.nested.iter() {
+                        match nested {
+                            syn::NestedMeta::Meta(syn::Meta::Path(path)) => {
+                                res.derives.push(path.into())
+                            }
+                            nested => res.other.push(nested.to_token_stream().to_string()),
+                        }
+                    }
+                }
+                _ => res.other.push(attr.to_token_stream().to_string()),
+            }
+        }
+        if !res.other.is_empty() {
+            log::warn!("keeping unknown attributes {:?} verbatim", res.other)
+        }
+        res
+    }
+}
+
+/// Fields of a struct
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RustFields {
+    /// `{ a: T, b: U }`
+    Named(
+        #[serde(with = "crate::serialize::ident_triples")]
+        Vec<(syn::Ident, RustVisibility, RustType)>,
+    ),
+    /// `(T, U)`
+    Unnamed(Vec<(RustVisibility, RustType)>),
+    Unit,
+}
+
+impl RustFields {
+    pub fn len(&self) -> usize {
+        match self {
+            RustFields::Named(fields) => fields.len(),
+            RustFields::Unnamed(fields) => fields.len(),
+            RustFields::Unit => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the types of the fields, in declaration order
+    pub fn types(&self) -> Vec<&RustType> {
+        match self {
+            RustFields::Named(fields) => fields.iter().map(|(_, _, ty)| ty).collect(),
+            RustFields::Unnamed(fields) => fields.iter().map(|(_, ty)| ty).collect(),
+            RustFields::Unit => vec![],
+        }
+    }
+
+    pub fn types_mut(&mut self) -> Vec<&mut RustType> {
+        match self {
+            RustFields::Named(fields) => fields.iter_mut().map(|(_, _, ty)| ty).collect(),
+            RustFields::Unnamed(fields) => fields.iter_mut().map(|(_, ty)| ty).collect(),
+            RustFields::Unit => vec![],
+        }
+    }
+
+    /// Returns the type of a field, accessed either by name or by position
+    pub fn get(&self, member: &syn::Member) -> Option<&RustType> {
+        match (self, member) {
+            (RustFields::Named(fields), syn::Member::Named(name)) => fields
+                .iter()
+                .find(|(id, _, _)| id == name)
+                .map(|(_, _, ty)| ty),
+            (RustFields::Unnamed(fields), syn::Member::Unnamed(idx)) => {
+                fields.get(idx.index as usize).map(|(_, ty)| ty)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<&syn::Fields> for RustFields {
+    type Error = Error;
+
+    fn try_from(fields: &syn::Fields) -> Result<Self, Self::Error> {
+        match fields {
+            syn::Fields::Named(FieldsNamed { named, .. }) => named
+                .iter()
+                .map(|v| {
+                    let ty = (&v.ty).try_into()?;
+                    Ok((v.ident.clone().unwrap(), (&v.vis).into(), ty))
+                })
+                .collect::<Result<_, Error>>()
+                .map(RustFields::Named),
+            syn::Fields::Unnamed(syn::FieldsUnnamed { unnamed, .. }) => unnamed
+                .iter()
+                .map(|v| Ok(((&v.vis).into(), (&v.ty).try_into()?)))
+                .collect::<Result<_, Error>>()
+                .map(RustFields::Unnamed),
+            syn::Fields::Unit => Ok(RustFields::Unit),
+        }
+    }
+}
+
+impl std::fmt::Display for RustFields {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RustFields::Named(fields) => write!(
+                f,
+                " {{ {} }}",
+                fields
+                    .iter()
+                    .map(|(name, vis, ty)| format!("{}{}: {}", vis, name, ty))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            RustFields::Unnamed(fields) => write!(
+                f,
+                "({});",
+                fields
+                    .iter()
+                    .map(|(vis, ty)| format!("{}{}", vis, ty))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            RustFields::Unit => write!(f, ";"),
+        }
+    }
+}
+
+/// Generic parameters of a type declaration
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RustGenerics {
+    /// lifetime parameters, along with the lifetimes they must outlive
+    pub lifetimes: Vec<(RustLifetime, Vec<RustLifetime>)>,
+    /// type parameters and their bounds, the i-th parameter is `TVar(i)` in the declaration
+    #[serde(with = "crate::serialize::ident_pairs")]
+    pub params: Vec<(syn::Ident, Vec<RustTypeConstraint>)>,
+}
+
+impl RustGenerics {
+    /// Replaces uses of the type parameters in `ty` by their type variables
+    pub(crate) fn abstract_params(&self, ty: &mut RustType) {
+        match ty {
+            RustType::CAlias(id) => {
+                if let Some(ind) = self.params.iter().position(|(param, _)| param == id) {
+                    *ty = RustType::TVar(TVar(ind))
+                }
+            }
+            ty => {
+                for child in ty.children_mut() {
+                    self.abstract_params(child)
+                }
+            }
+        }
+    }
+
+    /// Returns the substitution that instantiates the type parameters with `args`
+    pub fn instantiate(&self, args: &[RustType]) -> crate::unify::Substitution {
+        args.iter()
+            .take(self.params.len())
+            .enumerate()
+            .map(|(ind, arg)| (TVar(ind), arg.clone()))
+            .collect()
+    }
+}
+
+impl TryFrom<&syn::Generics> for RustGenerics {
+    type Error = Error;
+
+    fn try_from(generics: &syn::Generics) -> Result<Self, Self::Error> {
+        let mut res = RustGenerics::default();
+        for param in generics.params.iter() {
+            match param {
+                syn::GenericParam::Lifetime(param) => {
+                    let bounds = param.bounds.iter().map(|v| v.clone().into()).collect();
+                    res.lifetimes.push((param.lifetime.clone().into(), bounds))
+                }
+                syn::GenericParam::Type(param) => {
+                    let bounds = param
+                        .bounds
+                        .iter()
+                        .map(|v| v.try_into())
+                        .collect::<Result<_, _>>()?;
+                    res.params.push((param.ident.clone(), bounds))
+                }
+                param => return Err(Error::unsupported("generic parameter", param)),
+            }
+        }
+        for predicate in generics
+            .where_clause
+            .iter()
+            .flat_map(|v| v.predicates.iter())
+        {
+            match predicate {
+                syn::WherePredicate::Lifetime(pred) => {
+                    let Some((_, outlives)) = res
+                        .lifetimes
+                        .iter_mut()
+                        .find(|(lt, _)| pred.lifetime.ident == lt.0)
+                    else {
+                        return Err(Error::unsupported("where clause", pred));
+                    };
+                    outlives.extend(pred.bounds.iter().map(|v| v.clone().into()))
+                }
+                syn::WherePredicate::Type(syn::PredicateType {
+                    lifetimes: None,
+                    bounded_ty: Type::Path(syn::TypePath { qself: None, path }),
+                    bounds,
+                    ..
+                }) if path.get_ident().is_some() => {
+                    let ident = path.get_ident().unwrap();
+                    let Some((_, constraints)) =
+                        res.params.iter_mut().find(|(param, _)| param == ident)
+                    else {
+                        return Err(Error::unsupported("where clause", predicate));
+                    };
+                    for bound in bounds.iter() {
+                        constraints.push(bound.try_into()?)
+                    }
+                }
+                predicate => return Err(Error::unsupported("where clause", predicate)),
+            }
+        }
+        // bounds may mention the parameters too
+        let mut abstracted = res.clone();
+        for constraint in abstracted.params.iter_mut().flat_map(|(_, v)| v.iter_mut()) {
+            for ty in constraint.types_mut() {
+                res.abstract_params(ty)
+            }
+        }
+        Ok(abstracted)
+    }
+}
+
+impl std::fmt::Display for RustGenerics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.lifetimes.is_empty() && self.params.is_empty() {
+            return Ok(());
         }
+        let lifetimes = self.lifetimes.iter().map(|(lifetime, bounds)| {
+            let bounds = bounds
+                .iter()
+                .map(|v| format!("{}", v))
+                .collect::<Vec<_>>()
+                .join(" + ");
+            if bounds.is_empty() {
+                format!("{}", lifetime)
+            } else {
+                format!("{}: {}", lifetime, bounds)
+            }
+        });
+        let params = self.params.iter().enumerate().map(|(ind, (_, bounds))| {
+            let bounds = bounds
+                .iter()
+                .map(|v| format!("{}", v))
+                .collect::<Vec<_>>()
+                .join(" + ");
+            if bounds.is_empty() {
+                format!("{}", TVar(ind))
+            } else {
+                format!("{}: {}", TVar(ind), bounds)
+            }
+        });
+        let generics = lifetimes.chain(params).collect::<Vec<_>>().join(", ");
+        write!(f, "<{}>", generics)
     }
 }
 
-#[derive(Clone, Debug, Hash)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RustStruct {
+    #[serde(with = "crate::serialize::ident")]
     name: syn::Ident,
-    fields: Vec<(syn::Ident, RustType)>,
+    vis: RustVisibility,
+    generics: RustGenerics,
+    fields: RustFields,
+    attrs: RustAttributes,
 }
 
 impl std::fmt::Display for RustStruct {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "struct {} {{ {} }}",
-            self.name,
-            self.fields
-                .iter()
-                .map(|(name, ty)| format!("{}: {}", name, ty))
-                .collect::<Vec<_>>()
-                .join(",")
+            "{}struct {}{}{}",
+            self.vis, self.name, self.generics, self.fields
         )
     }
 }
@@ -609,327 +1636,2166 @@ impl RustStruct {
         &self.name
     }
 
-    pub fn fields(&self) -> &Vec<(syn::Ident, RustType)> {
+    pub fn vis(&self) -> &RustVisibility {
+        &self.vis
+    }
+
+    pub fn generics(&self) -> &RustGenerics {
+        &self.generics
+    }
+
+    pub fn fields(&self) -> &RustFields {
         &self.fields
     }
 
+    pub fn attrs(&self) -> &RustAttributes {
+        &self.attrs
+    }
+
+    /// Returns the type of a field of the struct instantiated with `args`, None if there is no
+    /// such field or the number of arguments does not match the type parameters
+    pub fn field_type(&self, member: &syn::Member, args: &[RustType]) -> Option<RustType> {
+        if args.len() != self.generics.params.len() {
+            return None;
+        }
+        let ty = self.fields.get(member)?;
+        Some(self.generics.instantiate(args).apply(ty))
+    }
+
     /// Returns a list of all the structs that this struct references
-    pub fn uses(&self) -> HashSet<syn::Ident> {
+    pub fn uses(&self) -> HashSet<RustPath> {
         let mut uses = HashSet::new();
-        for (_, ty) in self.fields.iter() {
+        for ty in self.fields.types() {
             ty.uses(&mut uses);
         }
         uses
     }
 
     /// Resolves a structs types, and returns the list of type names it references
-    pub fn resolve(&mut self, ctxt: &ProgramTypeContext) -> HashSet<syn::Ident> {
+    pub fn resolve(&mut self, ctxt: &ProgramTypeContext) -> HashSet<RustPath> {
         let mut acc = HashSet::new();
-        for (_, ty) in self.fields.iter_mut() {
+        for ty in self.fields.types_mut() {
             acc.extend(ty.resolve(ctxt).into_iter())
         }
         acc
     }
 }
 
+/// Panics on struct declarations that have no `RustStruct` representation, use
+/// `RustStruct::try_from` to handle them gracefully
 impl From<syn::ItemStruct> for RustStruct {
     fn from(i: syn::ItemStruct) -> Self {
-        if i.attrs.len() == 0 {
-            log::warn!("skipping unknown attributes {:?}", i.attrs)
-        }
-        if i.generics.params.len() > 0 {
-            panic!("found unsupported use of generic struct {:?} - @Bryan, if you are implementing lifetimes or something, talk to me first.",
-            i.generics)
-        }
+        RustStruct::try_from(&i).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
 
-        let syn::Fields::Named(FieldsNamed { named, .. }) = i.fields else {
-            panic!("found unsupported struct declaration {}", i.to_token_stream().to_string())
-        };
-        let name = i.ident;
-        let fields = named
-            .into_iter()
-            .map(|v| (v.ident.unwrap(), v.ty.into()))
-            .collect();
-        RustStruct { name, fields }
+impl TryFrom<&syn::ItemStruct> for RustStruct {
+    type Error = Error;
+
+    fn try_from(i: &syn::ItemStruct) -> Result<Self, Self::Error> {
+        let generics = RustGenerics::try_from(&i.generics)?;
+        let mut fields = RustFields::try_from(&i.fields)?;
+        for ty in fields.types_mut() {
+            generics.abstract_params(ty)
+        }
+        Ok(RustStruct {
+            name: i.ident.clone(),
+            vis: (&i.vis).into(),
+            generics,
+            fields,
+            attrs: i.attrs.as_slice().into(),
+        })
     }
 }
 
-impl RustStruct {}
+/// A variant of an enum, along with its explicit discriminant if any
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RustVariant {
+    #[serde(with = "crate::serialize::ident")]
+    pub name: syn::Ident,
+    pub fields: RustFields,
+    pub discriminant: Option<RustDiscriminant>,
+}
 
-#[derive(Clone, Debug, Hash)]
-pub enum RustTypeConstraint {
-    /// Index(T1, T2) represents Index<T1, Output=T2>
-    Index(RustType, RustType),
-    /// IndexMut(T1, T2) represents IndexMut<T1, Output=T2>
-    IndexMut(RustType, RustType),
+/// Explicit discriminant of an enum variant
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RustDiscriminant {
+    /// Value(n) represents a (possibly negated) integer literal
+    Value(i128),
+    /// Unknown(e) represents an expression that is not evaluated, such as a constant, by its
+    /// tokens
+    Unknown(String),
 }
 
-impl Into<syn::TypeParamBound> for RustTypeConstraint {
-    fn into(self) -> syn::TypeParamBound {
+impl std::fmt::Display for RustDiscriminant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            // Index<T1, Output=T2>
-            RustTypeConstraint::Index(t1, t2) => {
-                let mut path = syn::Path {
-                    leading_colon: None,
-                    segments: Punctuated::new(),
-                };
-                let mut args = Punctuated::new();
-                args.push(syn::GenericArgument::Type(t1.into()));
-                args.push(syn::GenericArgument::Binding(syn::Binding {
-                    ident: syn::parse_str::<syn::Ident>("Output").unwrap(),
-                    eq_token: Default::default(),
-                    ty: t2.into(),
-                }));
-
-                path.segments.push(syn::PathSegment {
-                    ident: syn::parse_str::<syn::Ident>("Index").unwrap(),
-                    arguments: syn::PathArguments::AngleBracketed(
-                        syn::AngleBracketedGenericArguments {
-                            colon2_token: None,
-                            lt_token: Default::default(),
-                            args,
-                            gt_token: Default::default(),
-                        },
-                    ),
-                });
+            RustDiscriminant::Value(v) => write!(f, "{}", v),
+            RustDiscriminant::Unknown(tokens) => write!(f, "{}", tokens),
+        }
+    }
+}
 
-                syn::TypeParamBound::Trait(syn::TraitBound {
-                    paren_token: None,
-                    modifier: syn::TraitBoundModifier::None,
-                    lifetimes: None,
-                    path,
-                })
-            }
-            RustTypeConstraint::IndexMut(t1, t2) => {
-                let mut path = syn::Path {
-                    leading_colon: None,
-                    segments: Punctuated::new(),
-                };
-                let mut args = Punctuated::new();
-                args.push(syn::GenericArgument::Type(t1.into()));
-                args.push(syn::GenericArgument::Binding(syn::Binding {
-                    ident: syn::parse_str::<syn::Ident>("Output").unwrap(),
-                    eq_token: Default::default(),
-                    ty: t2.into(),
-                }));
+impl TryFrom<&syn::Variant> for RustVariant {
+    type Error = Error;
 
-                path.segments.push(syn::PathSegment {
-                    ident: syn::parse_str::<syn::Ident>("IndexMut").unwrap(),
-                    arguments: syn::PathArguments::AngleBracketed(
-                        syn::AngleBracketedGenericArguments {
-                            colon2_token: None,
-                            lt_token: Default::default(),
-                            args,
-                            gt_token: Default::default(),
-                        },
-                    ),
+    fn try_from(v: &syn::Variant) -> Result<Self, Self::Error> {
+        let discriminant =
+            v.discriminant
+                .as_ref()
+                .map(|(_, expr)| match discriminant_value(expr) {
+                    Ok(v) => RustDiscriminant::Value(v),
+                    Err(_) => RustDiscriminant::Unknown(expr.to_token_stream().to_string()),
                 });
+        Ok(RustVariant {
+            name: v.ident.clone(),
+            fields: (&v.fields).try_into()?,
+            discriminant,
+        })
+    }
+}
 
-                syn::TypeParamBound::Trait(syn::TraitBound {
-                    paren_token: None,
-                    modifier: syn::TraitBoundModifier::None,
-                    lifetimes: None,
-                    path,
-                })
+/// Evaluates a discriminant, only (possibly negated) integer literals are supported
+fn discriminant_value(expr: &syn::Expr) -> Result<i128, Error> {
+    match expr {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Int(i),
+            ..
+        }) => i
+            .base10_parse()
+            .map_err(|_| Error::unsupported("discriminant", expr)),
+        syn::Expr::Unary(syn::ExprUnary {
+            op: syn::UnOp::Neg(_),
+            expr: inner,
+            ..
+        }) => discriminant_value(inner).map(|v| -v),
+        syn::Expr::Paren(syn::ExprParen { expr, .. })
+        | syn::Expr::Group(syn::ExprGroup { expr, .. }) => discriminant_value(expr),
+        expr => Err(Error::unsupported("discriminant", expr)),
+    }
+}
+
+impl std::fmt::Display for RustVariant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.fields {
+            // unit variants have no trailing `;`
+            RustFields::Unit => write!(f, "{}", self.name)?,
+            RustFields::Unnamed(_) => {
+                let fields = format!("{}", self.fields);
+                write!(f, "{}{}", self.name, fields.trim_end_matches(';'))?
             }
+            RustFields::Named(_) => write!(f, "{}{}", self.name, self.fields)?,
+        }
+        match &self.discriminant {
+            Some(discriminant) => write!(f, " = {}", discriminant),
+            None => Ok(()),
         }
     }
 }
 
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RustEnum {
+    #[serde(with = "crate::serialize::ident")]
+    name: syn::Ident,
+    vis: RustVisibility,
+    generics: RustGenerics,
+    variants: Vec<RustVariant>,
+    attrs: RustAttributes,
+}
+
+impl std::fmt::Display for RustEnum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}enum {}{} {{ {} }}",
+            self.vis,
+            self.name,
+            self.generics,
+            self.variants
+                .iter()
+                .map(|v| format!("{}", v))
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+    }
+}
+
+impl RustEnum {
+    pub fn name(&self) -> &syn::Ident {
+        &self.name
+    }
+
+    pub fn vis(&self) -> &RustVisibility {
+        &self.vis
+    }
+
+    pub fn generics(&self) -> &RustGenerics {
+        &self.generics
+    }
+
+    pub fn variants(&self) -> &Vec<RustVariant> {
+        &self.variants
+    }
+
+    pub fn attrs(&self) -> &RustAttributes {
+        &self.attrs
+    }
+
+    /// Returns a list of all the types that this enum references
+    pub fn uses(&self) -> HashSet<RustPath> {
+        let mut uses = HashSet::new();
+        for ty in self.variants.iter().flat_map(|v| v.fields.types()) {
+            ty.uses(&mut uses);
+        }
+        uses
+    }
+
+    /// Resolves the types of the variants, and returns the list of type names it references
+    pub fn resolve(&mut self, ctxt: &ProgramTypeContext) -> HashSet<RustPath> {
+        let mut acc = HashSet::new();
+        for ty in self.variants.iter_mut().flat_map(|v| v.fields.types_mut()) {
+            acc.extend(ty.resolve(ctxt))
+        }
+        acc
+    }
+}
+
+impl TryFrom<&syn::ItemEnum> for RustEnum {
+    type Error = Error;
+
+    fn try_from(i: &syn::ItemEnum) -> Result<Self, Self::Error> {
+        let generics = RustGenerics::try_from(&i.generics)?;
+        let mut variants = i
+            .variants
+            .iter()
+            .map(RustVariant::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        for ty in variants.iter_mut().flat_map(|v| v.fields.types_mut()) {
+            generics.abstract_params(ty)
+        }
+        Ok(RustEnum {
+            name: i.ident.clone(),
+            vis: (&i.vis).into(),
+            generics,
+            variants,
+            attrs: i.attrs.as_slice().into(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RustUnion {
+    #[serde(with = "crate::serialize::ident")]
+    name: syn::Ident,
+    vis: RustVisibility,
+    generics: RustGenerics,
+    /// always `RustFields::Named`
+    fields: RustFields,
+    attrs: RustAttributes,
+}
+
+impl std::fmt::Display for RustUnion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}union {}{}{}",
+            self.vis, self.name, self.generics, self.fields
+        )
+    }
+}
+
+impl RustUnion {
+    pub fn name(&self) -> &syn::Ident {
+        &self.name
+    }
+
+    pub fn vis(&self) -> &RustVisibility {
+        &self.vis
+    }
+
+    pub fn generics(&self) -> &RustGenerics {
+        &self.generics
+    }
+
+    pub fn fields(&self) -> &RustFields {
+        &self.fields
+    }
+
+    pub fn attrs(&self) -> &RustAttributes {
+        &self.attrs
+    }
+
+    /// Returns a list of all the types that this union references
+    pub fn uses(&self) -> HashSet<RustPath> {
+        let mut uses = HashSet::new();
+        for ty in self.fields.types() {
+            ty.uses(&mut uses);
+        }
+        uses
+    }
+
+    /// Resolves the types of the fields, and returns the list of type names it references
+    pub fn resolve(&mut self, ctxt: &ProgramTypeContext) -> HashSet<RustPath> {
+        let mut acc = HashSet::new();
+        for ty in self.fields.types_mut() {
+            acc.extend(ty.resolve(ctxt))
+        }
+        acc
+    }
+}
+
+impl TryFrom<&syn::ItemUnion> for RustUnion {
+    type Error = Error;
+
+    fn try_from(i: &syn::ItemUnion) -> Result<Self, Self::Error> {
+        let generics = RustGenerics::try_from(&i.generics)?;
+        let mut fields = RustFields::try_from(&syn::Fields::Named(i.fields.clone()))?;
+        for ty in fields.types_mut() {
+            generics.abstract_params(ty)
+        }
+        Ok(RustUnion {
+            name: i.ident.clone(),
+            vis: (&i.vis).into(),
+            generics,
+            fields,
+            attrs: i.attrs.as_slice().into(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RustTypeConstraint {
+    /// Index(T1, T2) represents Index<T1, Output=T2>
+    Index(RustType, RustType),
+    /// IndexMut(T1, T2) represents IndexMut<T1, Output=T2>
+    IndexMut(RustType, RustType),
+    /// Trait(P, [T1..], [(A, T)..]) represents P<T1.., A=T..>
+    Trait(
+        RustPath,
+        Vec<RustType>,
+        #[serde(with = "crate::serialize::ident_pairs")] Vec<(syn::Ident, RustType)>,
+    ),
+    /// Fn(P, [T1..], T) represents the sugared form P(T1..) -> T, for Fn, FnMut and FnOnce
+    Fn(RustPath, Vec<RustType>, Box<RustType>),
+    /// Outlives('a) represents a `'a` lifetime bound
+    Outlives(RustLifetime),
+    /// Maybe(P) represents the relaxed bound `?P`, i.e `?Sized`
+    Maybe(RustPath),
+}
+
+impl RustTypeConstraint {
+    /// Returns the types mentioned by the constraint
+    pub fn types(&self) -> Vec<&RustType> {
+        match self {
+            RustTypeConstraint::Index(t1, t2) | RustTypeConstraint::IndexMut(t1, t2) => {
+                vec![t1, t2]
+            }
+            RustTypeConstraint::Trait(_, args, bindings) => args
+                .iter()
+                .chain(bindings.iter().map(|(_, ty)| ty))
+                .collect(),
+            RustTypeConstraint::Fn(_, args, out) => {
+                args.iter().chain(std::iter::once(&**out)).collect()
+            }
+            RustTypeConstraint::Outlives(_) | RustTypeConstraint::Maybe(_) => vec![],
+        }
+    }
+
+    /// Returns mutable references to the types mentioned by the constraint
+    pub fn types_mut(&mut self) -> Vec<&mut RustType> {
+        match self {
+            RustTypeConstraint::Index(t1, t2) | RustTypeConstraint::IndexMut(t1, t2) => {
+                vec![t1, t2]
+            }
+            RustTypeConstraint::Trait(_, args, bindings) => args
+                .iter_mut()
+                .chain(bindings.iter_mut().map(|(_, ty)| ty))
+                .collect(),
+            RustTypeConstraint::Fn(_, args, out) => {
+                args.iter_mut().chain(std::iter::once(&mut **out)).collect()
+            }
+            RustTypeConstraint::Outlives(_) | RustTypeConstraint::Maybe(_) => vec![],
+        }
+    }
+}
+
+impl Into<syn::TypeParamBound> for RustTypeConstraint {
+    fn into(self) -> syn::TypeParamBound {
+        match self {
+            // Index<T1, Output=T2>
+            RustTypeConstraint::Index(t1, t2) => {
+                let mut path = syn::Path {
+                    leading_colon: None,
+                    segments: Punctuated::new(),
+                };
+                let mut args = Punctuated::new();
+                args.push(syn::GenericArgument::Type(t1.into()));
+                args.push(syn::GenericArgument::Binding(syn::Binding {
+                    ident: syn::parse_str::<syn::Ident>("Output").unwrap(),
+                    eq_token: Default::default(),
+                    ty: t2.into(),
+                }));
+
+                path.segments.push(syn::PathSegment {
+                    ident: syn::parse_str::<syn::Ident>("Index").unwrap(),
+                    arguments: syn::PathArguments::AngleBracketed(
+                        syn::AngleBracketedGenericArguments {
+                            colon2_token: None,
+                            lt_token: Default::default(),
+                            args,
+                            gt_token: Default::default(),
+                        },
+                    ),
+                });
+
+                syn::TypeParamBound::Trait(syn::TraitBound {
+                    paren_token: None,
+                    modifier: syn::TraitBoundModifier::None,
+                    lifetimes: None,
+                    path,
+                })
+            }
+            RustTypeConstraint::IndexMut(t1, t2) => {
+                let mut path = syn::Path {
+                    leading_colon: None,
+                    segments: Punctuated::new(),
+                };
+                let mut args = Punctuated::new();
+                args.push(syn::GenericArgument::Type(t1.into()));
+                args.push(syn::GenericArgument::Binding(syn::Binding {
+                    ident: syn::parse_str::<syn::Ident>("Output").unwrap(),
+                    eq_token: Default::default(),
+                    ty: t2.into(),
+                }));
+
+                path.segments.push(syn::PathSegment {
+                    ident: syn::parse_str::<syn::Ident>("IndexMut").unwrap(),
+                    arguments: syn::PathArguments::AngleBracketed(
+                        syn::AngleBracketedGenericArguments {
+                            colon2_token: None,
+                            lt_token: Default::default(),
+                            args,
+                            gt_token: Default::default(),
+                        },
+                    ),
+                });
+
+                syn::TypeParamBound::Trait(syn::TraitBound {
+                    paren_token: None,
+                    modifier: syn::TraitBoundModifier::None,
+                    lifetimes: None,
+                    path,
+                })
+            }
+            // P<T1.., A=T..>
+            RustTypeConstraint::Trait(path, args, bindings) => {
+                let mut path: syn::Path = path.into();
+                if !args.is_empty() || !bindings.is_empty() {
+                    let args = args
+                        .into_iter()
+                        .map(|ty| syn::GenericArgument::Type(ty.into()))
+                        .chain(bindings.into_iter().map(|(ident, ty)| {
+                            syn::GenericArgument::Binding(syn::Binding {
+                                ident,
+                                eq_token: Default::default(),
+                                ty: ty.into(),
+                            })
+                        }))
+                        .collect();
+                    path.segments.last_mut().unwrap().arguments =
+                        syn::PathArguments::AngleBracketed(syn::AngleBracketedGenericArguments {
+                            colon2_token: None,
+                            lt_token: Default::default(),
+                            args,
+                            gt_token: Default::default(),
+                        });
+                }
+                syn::TypeParamBound::Trait(syn::TraitBound {
+                    paren_token: None,
+                    modifier: syn::TraitBoundModifier::None,
+                    lifetimes: None,
+                    path,
+                })
+            }
+            // P(T1..) -> T
+            RustTypeConstraint::Fn(path, args, box out) => {
+                let mut path: syn::Path = path.into();
+                let output = match out {
+                    RustType::Unit => syn::ReturnType::Default,
+                    out => syn::ReturnType::Type(Default::default(), Box::new(out.into())),
+                };
+                path.segments.last_mut().unwrap().arguments =
+                    syn::PathArguments::Parenthesized(syn::ParenthesizedGenericArguments {
+                        paren_token: Default::default(),
+                        inputs: args.into_iter().map(|ty| -> Type { ty.into() }).collect(),
+                        output,
+                    });
+                syn::TypeParamBound::Trait(syn::TraitBound {
+                    paren_token: None,
+                    modifier: syn::TraitBoundModifier::None,
+                    lifetimes: None,
+                    path,
+                })
+            }
+            RustTypeConstraint::Outlives(lt) => syn::TypeParamBound::Lifetime(lt.into()),
+            RustTypeConstraint::Maybe(path) => syn::TypeParamBound::Trait(syn::TraitBound {
+                paren_token: None,
+                modifier: syn::TraitBoundModifier::Maybe(Default::default()),
+                lifetimes: None,
+                path: path.into(),
+            }),
+        }
+    }
+}
+
+/// Extracts `(T1, T2)` from the arguments of a bound of the form `Trait<T1, Output = T2>`
+fn index_bound_args(args: &syn::AngleBracketedGenericArguments) -> Option<(Type, Type)> {
+    match (args.args.len(), args.args.first(), args.args.last()) {
+        (
+            2,
+            Some(syn::GenericArgument::Type(in_ty)),
+            Some(syn::GenericArgument::Binding(binding)),
+        ) if binding.ident == "Output" => Some((in_ty.clone(), binding.ty.clone())),
+        _ => None,
+    }
+}
+
+/// Panics on bounds that have no `RustTypeConstraint` representation, use
+/// `RustTypeConstraint::try_from` to handle them gracefully
 impl From<syn::TypeParamBound> for RustTypeConstraint {
     fn from(ty: syn::TypeParamBound) -> Self {
-        match ty {
+        RustTypeConstraint::try_from(&ty).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+impl TryFrom<&syn::TypeParamBound> for RustTypeConstraint {
+    type Error = Error;
+
+    fn try_from(ty: &syn::TypeParamBound) -> Result<Self, Self::Error> {
+        let constraint = match ty {
+            syn::TypeParamBound::Lifetime(lt) => RustTypeConstraint::Outlives(lt.clone().into()),
             syn::TypeParamBound::Trait(syn::TraitBound {
-                path: syn::Path { segments, .. },
+                modifier: syn::TraitBoundModifier::Maybe(_),
+                lifetimes: None,
+                path,
                 ..
-            }) if segments.len() == 1 => {
-                let segment = segments[0].clone();
+            }) if path.segments.iter().all(|v| v.arguments.is_empty()) => {
+                RustTypeConstraint::Maybe(path.into())
+            }
+            bound @ syn::TypeParamBound::Trait(syn::TraitBound {
+                modifier: syn::TraitBoundModifier::Maybe(_),
+                ..
+            })
+            | bound @ syn::TypeParamBound::Trait(syn::TraitBound {
+                lifetimes: Some(_), ..
+            }) => return Err(Error::unsupported("type constraint", bound)),
+            syn::TypeParamBound::Trait(syn::TraitBound { path, .. }) => {
+                let segment = path.segments.last().unwrap();
                 let trait_name = segment.ident.to_string();
-                match (trait_name.as_str(), segment.arguments) {
-                    ("Index", syn::PathArguments::AngleBracketed(args)) => {
-                        let in_ty = match args.args[0].clone() {
-                            syn::GenericArgument::Type(ty) => ty,
-                            _ => panic!("invalid constraint structure {:#?}", segments),
-                        };
-                        let out_ty = match args.args[1].clone() {
-                            syn::GenericArgument::Binding(binding) => binding.ty,
-                            _ => panic!("invalid constraint structure {:#?}", segments),
-                        };
-                        RustTypeConstraint::Index(in_ty.into(), out_ty.into())
+                match (trait_name.as_str(), &segment.arguments) {
+                    ("Index", syn::PathArguments::AngleBracketed(args))
+                        if path.segments.len() == 1 && index_bound_args(args).is_some() =>
+                    {
+                        let (in_ty, out_ty) = index_bound_args(args).unwrap();
+                        RustTypeConstraint::Index((&in_ty).try_into()?, (&out_ty).try_into()?)
                     }
-                    ("IndexMut", syn::PathArguments::AngleBracketed(args)) => {
-                        let in_ty = match args.args[0].clone() {
-                            syn::GenericArgument::Type(ty) => ty,
-                            _ => panic!("invalid constraint structure {:#?}", segments),
-                        };
-                        let out_ty = match args.args[1].clone() {
-                            syn::GenericArgument::Binding(binding) => binding.ty,
-                            _ => panic!("invalid constraint structure {:#?}", segments),
+                    ("IndexMut", syn::PathArguments::AngleBracketed(args))
+                        if path.segments.len() == 1 && index_bound_args(args).is_some() =>
+                    {
+                        let (in_ty, out_ty) = index_bound_args(args).unwrap();
+                        RustTypeConstraint::IndexMut((&in_ty).try_into()?, (&out_ty).try_into()?)
+                    }
+                    (_, syn::PathArguments::None) => {
+                        RustTypeConstraint::Trait(path.into(), vec![], vec![])
+                    }
+                    (_, syn::PathArguments::AngleBracketed(args)) => {
+                        let mut tys = vec![];
+                        let mut bindings = vec![];
+                        for arg in args.args.iter() {
+                            match arg {
+                                syn::GenericArgument::Type(ty) => tys.push(ty.try_into()?),
+                                syn::GenericArgument::Binding(binding) => bindings
+                                    .push((binding.ident.clone(), (&binding.ty).try_into()?)),
+                                arg => return Err(Error::unsupported("generic argument", arg)),
+                            }
+                        }
+                        RustTypeConstraint::Trait(path.into(), tys, bindings)
+                    }
+                    (_, syn::PathArguments::Parenthesized(args)) => {
+                        let inputs = args
+                            .inputs
+                            .iter()
+                            .map(|ty| ty.try_into())
+                            .collect::<Result<_, _>>()?;
+                        let output = match &args.output {
+                            syn::ReturnType::Default => RustType::Unit,
+                            syn::ReturnType::Type(_, box ty) => ty.try_into()?,
                         };
-                        RustTypeConstraint::IndexMut(in_ty.into(), out_ty.into())
+                        RustTypeConstraint::Fn(path.into(), inputs, Box::new(output))
                     }
-                    _ => panic!("unsupported type constraint {:#?}", segments),
                 }
             }
-            ty => panic!("unsupported type constraint {:#?}", ty),
+        };
+        Ok(constraint)
+    }
+}
+
+impl std::fmt::Display for RustTypeConstraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RustTypeConstraint::Index(ind_ty, out_ty) => {
+                write!(f, "Index[{}] -> {}", ind_ty, out_ty)
+            }
+            RustTypeConstraint::IndexMut(ind_ty, out_ty) => {
+                write!(f, "IndexMut[{}] -> {}", ind_ty, out_ty)
+            }
+            RustTypeConstraint::Trait(path, args, bindings) => {
+                write!(f, "{}", path)?;
+                if !args.is_empty() || !bindings.is_empty() {
+                    let args = args
+                        .iter()
+                        .map(|ty| format!("{}", ty))
+                        .chain(bindings.iter().map(|(id, ty)| format!("{}={}", id, ty)))
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    write!(f, "<{}>", args)?;
+                }
+                Ok(())
+            }
+            RustTypeConstraint::Fn(path, args, out) => write!(
+                f,
+                "{}({}) -> {}",
+                path,
+                args.iter()
+                    .map(|ty| format!("{}", ty))
+                    .collect::<Vec<_>>()
+                    .join(","),
+                out
+            ),
+            RustTypeConstraint::Outlives(lt) => write!(f, "{}", lt),
+            RustTypeConstraint::Maybe(path) => write!(f, "?{}", path),
+        }
+    }
+}
+
+/// Receiver of a method signature
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RustReceiver {
+    /// `self`, or `mut self`
+    Value(RustMutability),
+    /// `&self`, `&'a mut self`
+    Reference(Option<RustLifetime>, RustMutability),
+    /// `self: Box<Self>`, or `mut self: Box<Self>`
+    Typed(RustMutability, RustType),
+}
+
+impl TryFrom<&syn::FnArg> for RustReceiver {
+    type Error = Error;
+
+    fn try_from(arg: &syn::FnArg) -> Result<Self, Self::Error> {
+        match arg {
+            syn::FnArg::Receiver(syn::Receiver {
+                reference: Some((_, lifetime)),
+                mutability,
+                ..
+            }) => Ok(RustReceiver::Reference(
+                lifetime.clone().map(|v| v.into()),
+                (*mutability).into(),
+            )),
+            syn::FnArg::Receiver(syn::Receiver { mutability, .. }) => {
+                Ok(RustReceiver::Value((*mutability).into()))
+            }
+            syn::FnArg::Typed(syn::PatType {
+                pat:
+                    box syn::Pat::Ident(syn::PatIdent {
+                        by_ref: None,
+                        mutability,
+                        ident,
+                        subpat: None,
+                        ..
+                    }),
+                ty: box ty,
+                ..
+            }) if ident == "self" => Ok(RustReceiver::Typed((*mutability).into(), ty.try_into()?)),
+            arg => Err(Error::unsupported("receiver", arg)),
+        }
+    }
+}
+
+/// Returns true for `self` arguments, including typed ones such as `self: Rc<Self>`
+fn is_receiver(arg: &syn::FnArg) -> bool {
+    match arg {
+        syn::FnArg::Receiver(_) => true,
+        syn::FnArg::Typed(syn::PatType {
+            pat: box syn::Pat::Ident(syn::PatIdent { ident, .. }),
+            ..
+        }) => ident == "self",
+        _ => false,
+    }
+}
+
+impl std::fmt::Display for RustReceiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let prefix = |mutability: &RustMutability| match mutability {
+            RustMutability::Mutable => "mut ",
+            RustMutability::Immutable => "",
+        };
+        match self {
+            RustReceiver::Value(mutability) => write!(f, "{}self", prefix(mutability)),
+            RustReceiver::Reference(None, mutability) => write!(f, "&{}self", prefix(mutability)),
+            RustReceiver::Reference(Some(lt), mutability) => {
+                write!(f, "&{} {}self", lt, prefix(mutability))
+            }
+            RustReceiver::Typed(mutability, ty) => write!(f, "{}self: {}", prefix(mutability), ty),
+        }
+    }
+}
+
+/// Irrefutable pattern binding the arguments of a function
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RustPattern {
+    /// `x`, `mut x`, `ref x`, `ref mut x`
+    Ident {
+        by_ref: bool,
+        mutability: RustMutability,
+        #[serde(with = "crate::serialize::ident")]
+        name: syn::Ident,
+    },
+    /// `_`
+    Wild,
+    /// `..`, within tuple and slice patterns
+    Rest,
+    /// `&p`, `&mut p`
+    Reference(RustMutability, Box<RustPattern>),
+    /// `(p1, p2)`
+    Tuple(Vec<RustPattern>),
+    /// `[p1, p2]`
+    Slice(Vec<RustPattern>),
+    /// `S(p1, p2)`
+    TupleStruct(RustPath, Vec<RustPattern>),
+    /// `S { a: p1, b: p2, .. }`, true when the remaining fields are ignored with `..`
+    Struct(
+        RustPath,
+        #[serde(with = "crate::serialize::ident_pairs")] Vec<(syn::Ident, RustPattern)>,
+        bool,
+    ),
+}
+
+impl RustPattern {
+    /// Returns the variables bound by the pattern, from left to right
+    pub fn bindings(&self) -> Vec<&syn::Ident> {
+        match self {
+            RustPattern::Ident { name, .. } => vec![name],
+            RustPattern::Wild | RustPattern::Rest => vec![],
+            RustPattern::Reference(_, pat) => pat.bindings(),
+            RustPattern::Tuple(pats)
+            | RustPattern::Slice(pats)
+            | RustPattern::TupleStruct(_, pats) => pats.iter().flat_map(|v| v.bindings()).collect(),
+            RustPattern::Struct(_, fields, _) => {
+                fields.iter().flat_map(|(_, v)| v.bindings()).collect()
+            }
+        }
+    }
+}
+
+impl TryFrom<&syn::Pat> for RustPattern {
+    type Error = Error;
+
+    fn try_from(pat: &syn::Pat) -> Result<Self, Self::Error> {
+        let all = |pats: &Punctuated<syn::Pat, syn::token::Comma>| {
+            pats.iter()
+                .map(|v| v.try_into())
+                .collect::<Result<Vec<_>, _>>()
+        };
+        match pat {
+            syn::Pat::Ident(syn::PatIdent {
+                by_ref,
+                mutability,
+                ident,
+                subpat: None,
+                ..
+            }) => Ok(RustPattern::Ident {
+                by_ref: by_ref.is_some(),
+                mutability: (*mutability).into(),
+                name: ident.clone(),
+            }),
+            syn::Pat::Wild(_) => Ok(RustPattern::Wild),
+            syn::Pat::Rest(_) => Ok(RustPattern::Rest),
+            syn::Pat::Reference(syn::PatReference {
+                mutability, pat, ..
+            }) => Ok(RustPattern::Reference(
+                (*mutability).into(),
+                Box::new((&**pat).try_into()?),
+            )),
+            syn::Pat::Tuple(syn::PatTuple { elems, .. }) => Ok(RustPattern::Tuple(all(elems)?)),
+            syn::Pat::Slice(syn::PatSlice { elems, .. }) => Ok(RustPattern::Slice(all(elems)?)),
+            syn::Pat::TupleStruct(syn::PatTupleStruct { path, pat, .. }) => {
+                Ok(RustPattern::TupleStruct(path.into(), all(&pat.elems)?))
+            }
+            syn::Pat::Struct(syn::PatStruct {
+                path,
+                fields,
+                dot2_token,
+                ..
+            }) => {
+                let fields = fields
+                    .iter()
+                    .map(|field| match &field.member {
+                        syn::Member::Named(name) => Ok((name.clone(), (&*field.pat).try_into()?)),
+                        syn::Member::Unnamed(_) => Err(Error::unsupported("pattern", field)),
+                    })
+                    .collect::<Result<_, Error>>()?;
+                Ok(RustPattern::Struct(
+                    path.into(),
+                    fields,
+                    dot2_token.is_some(),
+                ))
+            }
+            pat => Err(Error::unsupported("pattern", pat)),
+        }
+    }
+}
+
+impl std::fmt::Display for RustPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |pats: &Vec<RustPattern>| {
+            pats.iter()
+                .map(|v| format!("{}", v))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            RustPattern::Ident {
+                by_ref,
+                mutability,
+                name,
+            } => {
+                if *by_ref {
+                    write!(f, "ref ")?;
+                }
+                if *mutability == RustMutability::Mutable {
+                    write!(f, "mut ")?;
+                }
+                write!(f, "{}", name)
+            }
+            RustPattern::Wild => write!(f, "_"),
+            RustPattern::Rest => write!(f, ".."),
+            RustPattern::Reference(RustMutability::Immutable, pat) => write!(f, "&{}", pat),
+            RustPattern::Reference(RustMutability::Mutable, pat) => write!(f, "&mut {}", pat),
+            RustPattern::Tuple(pats) if pats.len() == 1 => write!(f, "({},)", pats[0]),
+            RustPattern::Tuple(pats) => write!(f, "({})", list(pats)),
+            RustPattern::Slice(pats) => write!(f, "[{}]", list(pats)),
+            RustPattern::TupleStruct(path, pats) => write!(f, "{}({})", path, list(pats)),
+            RustPattern::Struct(path, fields, rest) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, pat)| format!("{}: {}", name, pat))
+                    .chain(rest.then(|| "..".to_string()))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "{} {{ {} }}", path, fields)
+            }
+        }
+    }
+}
+
+/// `where` predicates of a signature that do not bound one of its own parameters
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RustWherePredicate {
+    /// `'a: 'b + 'c`
+    Lifetime(RustLifetime, Vec<RustLifetime>),
+    /// `Vec<T0>: Clone + Send`
+    Type(RustType, Vec<RustTypeConstraint>),
+}
+
+impl std::fmt::Display for RustWherePredicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (bounded, bounds) = match self {
+            RustWherePredicate::Lifetime(lt, bounds) => (
+                format!("{}", lt),
+                bounds.iter().map(|v| format!("{}", v)).collect::<Vec<_>>(),
+            ),
+            RustWherePredicate::Type(ty, bounds) => (
+                format!("{}", ty),
+                bounds.iter().map(|v| format!("{}", v)).collect::<Vec<_>>(),
+            ),
+        };
+        write!(f, "{}: {}", bounded, bounds.join(" + "))
+    }
+}
+
+/// Qualifiers written before the `fn` keyword of a signature
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RustQualifiers {
+    pub constness: bool,
+    pub asyncness: bool,
+    pub unsafety: bool,
+    pub abi: RustAbi,
+}
+
+impl Default for RustQualifiers {
+    fn default() -> Self {
+        RustQualifiers {
+            constness: false,
+            asyncness: false,
+            unsafety: false,
+            abi: RustAbi::Rust,
+        }
+    }
+}
+
+impl From<&syn::Signature> for RustQualifiers {
+    fn from(sig: &syn::Signature) -> Self {
+        RustQualifiers {
+            constness: sig.constness.is_some(),
+            asyncness: sig.asyncness.is_some(),
+            unsafety: sig.unsafety.is_some(),
+            abi: sig.abi.clone().into(),
+        }
+    }
+}
+
+impl std::fmt::Display for RustQualifiers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.constness {
+            write!(f, "const ")?;
+        }
+        if self.asyncness {
+            write!(f, "async ")?;
+        }
+        if self.unsafety {
+            write!(f, "unsafe ")?;
+        }
+        match &self.abi {
+            RustAbi::Rust => Ok(()),
+            RustAbi::Extern(None) => write!(f, "extern "),
+            RustAbi::Extern(Some(name)) => write!(f, "extern {:?} ", name),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RustTypeSignature {
+    name: String,
+    qualifiers: RustQualifiers,
+    /// lifetime parameters, along with the lifetimes they must outlive
+    lifetimes: Vec<(RustLifetime, Vec<RustLifetime>)>,
+    /// type parameters and their bounds, the i-th parameter of a parsed signature is `TVar(i)`
+    constraints: Vec<(TVar, Vec<RustTypeConstraint>)>,
+    /// names the parameters had in the source, used when converting back to syn
+    #[serde(default, with = "crate::serialize::ident_pairs")]
+    param_names: Vec<(syn::Ident, TVar)>,
+    /// `where` predicates on types and lifetimes that are not parameters of the signature,
+    /// predicates on its own parameters are merged into `lifetimes` and `constraints`
+    predicates: Vec<RustWherePredicate>,
+    receiver: Option<RustReceiver>,
+    args: Vec<(RustPattern, RustType)>,
+    /// whether the arguments end with the `...` of a C variadic function
+    #[serde(default)]
+    variadic: bool,
+    out_ty: Option<RustType>,
+}
+
+impl RustTypeSignature {
+    pub fn qualifiers(&self) -> &RustQualifiers {
+        &self.qualifiers
+    }
+
+    pub fn lifetimes(&self) -> &Vec<(RustLifetime, Vec<RustLifetime>)> {
+        &self.lifetimes
+    }
+
+    pub fn constraints(&self) -> &Vec<(TVar, Vec<RustTypeConstraint>)> {
+        &self.constraints
+    }
+
+    pub fn predicates(&self) -> &Vec<RustWherePredicate> {
+        &self.predicates
+    }
+
+    /// Receiver of the signature, None if it is not a method
+    pub fn receiver(&self) -> Option<&RustReceiver> {
+        self.receiver.as_ref()
+    }
+
+    /// Arguments of the function, excluding the receiver
+    pub fn args(&self) -> &Vec<(RustPattern, RustType)> {
+        &self.args
+    }
+
+    /// Returns true if the function accepts more arguments than `args`, like C's `printf`
+    pub fn is_variadic(&self) -> bool {
+        self.variadic
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return type of the function, None if it returns `()` implicitly
+    pub fn out_ty(&self) -> Option<&RustType> {
+        self.out_ty.as_ref()
+    }
+
+    /// Builds a non-generic, unqualified function signature
+    pub fn new(
+        name: impl Into<String>,
+        args: Vec<(RustPattern, RustType)>,
+        out_ty: Option<RustType>,
+    ) -> Self {
+        RustTypeSignature {
+            name: name.into(),
+            qualifiers: RustQualifiers::default(),
+            lifetimes: vec![],
+            constraints: vec![],
+            param_names: vec![],
+            predicates: vec![],
+            receiver: None,
+            args,
+            variadic: false,
+            out_ty,
+        }
+    }
+
+    /// Returns the types of the receiver, arguments and output of the signature, in order
+    pub fn types(&self) -> Vec<&RustType> {
+        let receiver = match &self.receiver {
+            Some(RustReceiver::Typed(_, ty)) => Some(ty),
+            _ => None,
+        };
+        receiver
+            .into_iter()
+            .chain(self.args.iter().map(|(_, ty)| ty))
+            .chain(self.out_ty.iter())
+            .collect()
+    }
+
+    /// Mutable references to every type of the signature, including the ones in bounds
+    fn types_mut(&mut self) -> Vec<&mut RustType> {
+        let receiver = match &mut self.receiver {
+            Some(RustReceiver::Typed(_, ty)) => Some(ty),
+            _ => None,
+        };
+        let bounds = self
+            .constraints
+            .iter_mut()
+            .flat_map(|(_, bounds)| bounds.iter_mut())
+            .flat_map(|bound| bound.types_mut());
+        let predicates = self.predicates.iter_mut().flat_map(|pred| match pred {
+            RustWherePredicate::Lifetime(..) => vec![],
+            RustWherePredicate::Type(ty, bounds) => std::iter::once(ty)
+                .chain(bounds.iter_mut().flat_map(|bound| bound.types_mut()))
+                .collect(),
+        });
+        receiver
+            .into_iter()
+            .chain(self.args.iter_mut().map(|(_, ty)| ty))
+            .chain(self.out_ty.iter_mut())
+            .chain(bounds)
+            .chain(predicates)
+            .collect()
+    }
+
+    /// Returns the type variables occurring in the receiver, arguments and output of the
+    /// signature, in order of first occurrence
+    pub fn free_vars(&self) -> Vec<TVar> {
+        let mut vars = vec![];
+        for ty in self.types() {
+            for var in ty.free_vars() {
+                if !vars.contains(&var) {
+                    vars.push(var)
+                }
+            }
+        }
+        vars
+    }
+
+    /// Applies `subst` to every type of the signature. Parameters renamed to another type
+    /// variable keep their bounds, while parameters bound to any other type stop being generic:
+    /// use `TraitEnv::check` beforehand to check their bounds.
+    pub fn substitute(&self, subst: &crate::unify::Substitution) -> RustTypeSignature {
+        let mut sig = self.clone();
+        for ty in sig.types_mut() {
+            *ty = subst.apply(ty)
+        }
+        sig.constraints = sig
+            .constraints
+            .into_iter()
+            .filter_map(|(tvar, bounds)| match subst.apply(&RustType::TVar(tvar)) {
+                RustType::TVar(tvar) => Some((tvar, bounds)),
+                _ => None,
+            })
+            .collect();
+        sig.param_names = sig
+            .param_names
+            .into_iter()
+            .filter_map(|(name, tvar)| match subst.apply(&RustType::TVar(tvar)) {
+                RustType::TVar(tvar) => Some((name, tvar)),
+                _ => None,
+            })
+            .collect();
+        sig
+    }
+
+    /// Instantiates the generic parameters of the signature with fresh types, e.g at a call
+    /// site, returning the instantiated signature and the substitution used
+    pub fn instantiate(
+        &self,
+        mut fresh: impl FnMut() -> RustType,
+    ) -> (RustTypeSignature, crate::unify::Substitution) {
+        let subst: crate::unify::Substitution = self
+            .constraints
+            .iter()
+            .map(|(tvar, _)| (*tvar, fresh()))
+            .collect();
+        (self.substitute(&subst), subst)
+    }
+
+    /// Turns the type variables left in an inferred signature into generic parameters
+    /// `T0..Tn`, numbered in order of first occurrence, bounded by the constraints collected
+    /// for them in `bounds`. Bounds on variables that do not occur in the signature are dropped.
+    pub fn generalise(&self, bounds: &[(TVar, Vec<RustTypeConstraint>)]) -> RustTypeSignature {
+        let mut vars = self.free_vars();
+        // parameters the signature already had keep their bounds, even if unused
+        for (tvar, _) in self.constraints.iter() {
+            if !vars.contains(tvar) {
+                vars.push(*tvar)
+            }
+        }
+        let rename: crate::unify::Substitution = vars
+            .iter()
+            .enumerate()
+            .map(|(ind, tvar)| (*tvar, RustType::TVar(TVar(ind))))
+            .collect();
+        let mut sig = self.clone();
+        for ty in sig.types_mut() {
+            // renaming in one pass, as the renamed variables may overlap the original ones
+            *ty = rename_vars(ty, &rename)
+        }
+        let mut constraints = vars
+            .iter()
+            .enumerate()
+            .map(|(ind, _)| (TVar(ind), vec![]))
+            .collect::<Vec<(TVar, Vec<RustTypeConstraint>)>>();
+        let collected = self.constraints.iter().chain(bounds.iter());
+        for (tvar, bounds) in collected {
+            let Some(ind) = vars.iter().position(|var| var == tvar) else {
+                continue;
+            };
+            for bound in bounds.iter() {
+                let mut bound = bound.clone();
+                for ty in bound.types_mut() {
+                    *ty = rename_vars(ty, &rename)
+                }
+                if !constraints[ind].1.contains(&bound) {
+                    constraints[ind].1.push(bound)
+                }
+            }
+        }
+        sig.constraints = constraints;
+        sig.param_names = vec![];
+        sig
+    }
+
+    /// Replaces every occurrence of the concrete type `target` by a fresh type parameter,
+    /// returning the generalised signature and the new parameter
+    pub fn abstract_over(&self, target: &RustType) -> (RustTypeSignature, TVar) {
+        let mut sig = self.clone();
+        let mut next = sig.constraints.iter().map(|(tvar, _)| tvar.0 + 1).max();
+        for ty in sig.types_mut() {
+            next = ty
+                .free_vars()
+                .into_iter()
+                .map(|var| var.0 + 1)
+                .chain(next)
+                .max();
+        }
+        let tvar = TVar(next.unwrap_or(0));
+        for ty in sig.types_mut() {
+            *ty = ty.abstract_over(target, tvar)
+        }
+        sig.constraints.push((tvar, vec![]));
+        (sig, tvar)
+    }
+}
+
+/// Applies a renaming of type variables without following chains of bindings, unlike
+/// `Substitution::apply`
+fn rename_vars(ty: &RustType, rename: &crate::unify::Substitution) -> RustType {
+    match ty {
+        RustType::TVar(tvar) => rename.get(tvar).cloned().unwrap_or(RustType::TVar(*tvar)),
+        ty => {
+            let mut ty = ty.clone();
+            for child in ty.children_mut() {
+                *child = rename_vars(child, rename)
+            }
+            ty
+        }
+    }
+}
+
+/// Panics on signatures that have no `RustTypeSignature` representation, use
+/// `RustTypeSignature::try_from` to handle them gracefully
+impl From<syn::Signature> for RustTypeSignature {
+    fn from(sig: syn::Signature) -> Self {
+        RustTypeSignature::try_from(&sig).unwrap_or_else(|err| panic!("{}", err))
+    }
+}
+
+impl TryFrom<&syn::Signature> for RustTypeSignature {
+    type Error = Error;
+
+    fn try_from(sig: &syn::Signature) -> Result<Self, Self::Error> {
+        let name = sig.ident.to_string();
+        let mut lifetimes = sig
+            .generics
+            .lifetimes()
+            .map(|param| {
+                let bounds = param.bounds.iter().map(|v| v.clone().into()).collect();
+                (param.lifetime.clone().into(), bounds)
+            })
+            .collect::<Vec<(RustLifetime, Vec<RustLifetime>)>>();
+        // parameters are numbered by position, whatever their names
+        let param_names = sig
+            .generics
+            .type_params()
+            .enumerate()
+            .map(|(ind, param)| (param.ident.clone(), TVar(ind)))
+            .collect::<Vec<_>>();
+        let mut constraints = sig
+            .generics
+            .type_params()
+            .zip(param_names.iter())
+            .map(|(param, (_, tvar))| {
+                let bounds = param
+                    .bounds
+                    .iter()
+                    .map(|v| v.try_into())
+                    .collect::<Result<_, _>>()?;
+                Ok((*tvar, bounds))
+            })
+            .collect::<Result<Vec<(TVar, Vec<RustTypeConstraint>)>, Error>>()?;
+        if let Some(param) = sig.generics.const_params().next() {
+            return Err(Error::unsupported("generic parameter", param));
+        }
+        let mut predicates = vec![];
+        for predicate in sig
+            .generics
+            .where_clause
+            .iter()
+            .flat_map(|v| v.predicates.iter())
+        {
+            match predicate {
+                syn::WherePredicate::Lifetime(pred) => {
+                    let lifetime: RustLifetime = pred.lifetime.clone().into();
+                    let bounds = pred.bounds.iter().map(|v| v.clone().into());
+                    match lifetimes.iter_mut().find(|(lt, _)| *lt == lifetime) {
+                        Some((_, outlives)) => outlives.extend(bounds),
+                        None => predicates
+                            .push(RustWherePredicate::Lifetime(lifetime, bounds.collect())),
+                    }
+                }
+                syn::WherePredicate::Type(syn::PredicateType {
+                    lifetimes: None,
+                    bounded_ty,
+                    bounds,
+                    ..
+                }) => {
+                    let ty: RustType = bounded_ty.try_into()?;
+                    let bounds = bounds
+                        .iter()
+                        .map(|v| v.try_into())
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let param = |tvar: &TVar| match &ty {
+                        RustType::CAlias(id) => param_names.contains(&(id.clone(), *tvar)),
+                        _ => false,
+                    };
+                    match constraints.iter_mut().find(|(tvar, _)| param(tvar)) {
+                        Some((_, constraints)) => constraints.extend(bounds),
+                        None => predicates.push(RustWherePredicate::Type(ty, bounds)),
+                    }
+                }
+                predicate => return Err(Error::unsupported("where clause", predicate)),
+            }
+        }
+        let mut inputs = sig.inputs.iter().peekable();
+        let receiver = match inputs.peek() {
+            Some(arg) if is_receiver(arg) => Some(RustReceiver::try_from(*arg)?),
+            _ => None,
+        };
+        if receiver.is_some() {
+            inputs.next();
+        }
+        let args = inputs
+            .map(|arg| match arg {
+                syn::FnArg::Typed(syn::PatType { pat, ty, .. }) if !is_receiver(arg) => {
+                    Ok(((&**pat).try_into()?, (&**ty).try_into()?))
+                }
+                arg => Err(Error::unsupported("receiver", arg)),
+            })
+            .collect::<Result<_, Error>>()?;
+        let mut sig = RustTypeSignature {
+            name,
+            qualifiers: sig.into(),
+            lifetimes,
+            constraints,
+            param_names,
+            predicates,
+            receiver,
+            args,
+            variadic: sig.variadic.is_some(),
+            out_ty: match &sig.output {
+                syn::ReturnType::Default => None,
+                syn::ReturnType::Type(_, box ty) => Some(ty.try_into()?),
+            },
+        };
+        // uses of the parameters are parsed as nominal types named after them
+        let params = sig.param_names.clone();
+        for ty in sig.types_mut() {
+            abstract_tvars(ty, &params)
+        }
+        Ok(sig)
+    }
+}
+
+/// Replaces the nominal types named after one of `params` by its type variable
+fn abstract_tvars(ty: &mut RustType, params: &[(syn::Ident, TVar)]) {
+    match ty {
+        RustType::CAlias(id) => {
+            if let Some((_, tvar)) = params.iter().find(|(name, _)| name == id) {
+                *ty = RustType::TVar(*tvar)
+            }
+        }
+        ty => {
+            for child in ty.children_mut() {
+                abstract_tvars(child, params)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for RustTypeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}fn {}<", self.qualifiers, self.name)?;
+
+        for (i, (lifetime, bounds)) in self.lifetimes.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", lifetime)?;
+            if !bounds.is_empty() {
+                let bounds = bounds
+                    .iter()
+                    .map(|v| format!("{}", v))
+                    .collect::<Vec<_>>()
+                    .join(" + ");
+                write!(f, ": {}", bounds)?;
+            }
+        }
+        if !self.lifetimes.is_empty() && !self.constraints.is_empty() {
+            write!(f, ", ")?;
+        }
+
+        {
+            let constraints = self.constraints.iter().map(|v| Some(v)).intersperse(None);
+            for constraint in constraints {
+                match constraint {
+                    None => write!(f, ", ")?,
+                    Some((tvar, constraints)) => {
+                        write!(f, "{}: ", tvar)?;
+                        let constraints = constraints
+                            .iter()
+                            .map(|v| format!("{}", v))
+                            .intersperse(" + ".into());
+                        for opt in constraints {
+                            write!(f, "{}", opt)?
+                        }
+                    }
+                }
+            }
+        }
+        write!(f, ">(")?;
+        if let Some(receiver) = &self.receiver {
+            write!(f, "{}", receiver)?;
+            if !self.args.is_empty() {
+                write!(f, ",")?;
+            }
+        }
+        {
+            let mut args = self.args.iter();
+            let mut next = args.next();
+            while next.is_some() {
+                let (name, ty) = next.unwrap();
+                write!(f, "{}: {}", name, ty)?;
+                next = args.next();
+                if next.is_some() {
+                    write!(f, ",")?;
+                }
+            }
+        }
+        if self.variadic {
+            if self.receiver.is_some() || !self.args.is_empty() {
+                write!(f, ",")?;
+            }
+            write!(f, "...")?;
+        }
+        write!(f, ")")?;
+        if let Some(ty) = &self.out_ty {
+            write!(f, " -> {}", ty)?;
+        }
+        if !self.predicates.is_empty() {
+            let predicates = self
+                .predicates
+                .iter()
+                .map(|v| format!("{}", v))
+                .collect::<Vec<_>>()
+                .join(", ");
+            write!(f, " where {}", predicates)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<RustReceiver> for syn::FnArg {
+    fn from(receiver: RustReceiver) -> Self {
+        let (reference, mutability) = match receiver {
+            RustReceiver::Value(mutability) => (None, mutability),
+            RustReceiver::Reference(lt, mutability) => {
+                (Some((Default::default(), lt.map(|v| v.into()))), mutability)
+            }
+            RustReceiver::Typed(mutability, ty) => {
+                let pat = RustPattern::Ident {
+                    by_ref: false,
+                    mutability,
+                    name: syn::Ident::new("self", proc_macro2::Span::call_site()),
+                };
+                return syn::FnArg::Typed(syn::PatType {
+                    attrs: vec![],
+                    pat: Box::new(pat.into()),
+                    colon_token: Default::default(),
+                    ty: Box::new(ty.into()),
+                });
+            }
+        };
+        syn::FnArg::Receiver(syn::Receiver {
+            attrs: vec![],
+            reference,
+            mutability: mutability.into(),
+            self_token: Default::default(),
+        })
+    }
+}
+
+impl From<RustPattern> for syn::Pat {
+    fn from(pat: RustPattern) -> Self {
+        let all = |pats: Vec<RustPattern>| {
+            let mut elems: Punctuated<syn::Pat, syn::token::Comma> =
+                pats.into_iter().map(syn::Pat::from).collect();
+            // `(x,)` is a tuple, `(x)` a parenthesized pattern
+            if elems.len() == 1 {
+                elems.push_punct(Default::default())
+            }
+            elems
+        };
+        match pat {
+            RustPattern::Ident {
+                by_ref,
+                mutability,
+                name,
+            } => syn::Pat::Ident(syn::PatIdent {
+                attrs: vec![],
+                by_ref: by_ref.then(Default::default),
+                mutability: mutability.into(),
+                ident: name,
+                subpat: None,
+            }),
+            RustPattern::Wild => syn::Pat::Wild(syn::PatWild {
+                attrs: vec![],
+                underscore_token: Default::default(),
+            }),
+            RustPattern::Rest => syn::Pat::Rest(syn::PatRest {
+                attrs: vec![],
+                dot2_token: Default::default(),
+            }),
+            RustPattern::Reference(mutability, box pat) => syn::Pat::Reference(syn::PatReference {
+                attrs: vec![],
+                and_token: Default::default(),
+                mutability: mutability.into(),
+                pat: Box::new(pat.into()),
+            }),
+            RustPattern::Tuple(pats) => syn::Pat::Tuple(syn::PatTuple {
+                attrs: vec![],
+                paren_token: Default::default(),
+                elems: all(pats),
+            }),
+            RustPattern::Slice(pats) => syn::Pat::Slice(syn::PatSlice {
+                attrs: vec![],
+                bracket_token: Default::default(),
+                elems: pats.into_iter().map(syn::Pat::from).collect(),
+            }),
+            RustPattern::TupleStruct(path, pats) => syn::Pat::TupleStruct(syn::PatTupleStruct {
+                attrs: vec![],
+                path: path.into(),
+                pat: syn::PatTuple {
+                    attrs: vec![],
+                    paren_token: Default::default(),
+                    elems: pats.into_iter().map(syn::Pat::from).collect(),
+                },
+            }),
+            RustPattern::Struct(path, fields, rest) => syn::Pat::Struct(syn::PatStruct {
+                attrs: vec![],
+                path: path.into(),
+                brace_token: Default::default(),
+                fields: fields
+                    .into_iter()
+                    .map(|(name, pat)| syn::FieldPat {
+                        attrs: vec![],
+                        member: syn::Member::Named(name),
+                        colon_token: Some(Default::default()),
+                        pat: Box::new(pat.into()),
+                    })
+                    .collect(),
+                dot2_token: rest.then(Default::default),
+            }),
+        }
+    }
+}
+
+impl From<RustWherePredicate> for syn::WherePredicate {
+    fn from(predicate: RustWherePredicate) -> Self {
+        match predicate {
+            RustWherePredicate::Lifetime(lt, bounds) => {
+                syn::WherePredicate::Lifetime(syn::PredicateLifetime {
+                    lifetime: lt.into(),
+                    colon_token: Default::default(),
+                    bounds: bounds.into_iter().map(syn::Lifetime::from).collect(),
+                })
+            }
+            RustWherePredicate::Type(ty, bounds) => syn::WherePredicate::Type(syn::PredicateType {
+                lifetimes: None,
+                bounded_ty: ty.into(),
+                colon_token: Default::default(),
+                bounds: bounds
+                    .into_iter()
+                    .map(Into::<syn::TypeParamBound>::into)
+                    .collect(),
+            }),
+        }
+    }
+}
+
+impl From<RustTypeSignature> for syn::Signature {
+    fn from(mut sig: RustTypeSignature) -> Self {
+        let span = proc_macro2::Span::call_site();
+        // parameters get back their source names, the others are named `T<n>` unless a source
+        // name is already taken
+        let mut names = vec![];
+        for (tvar, _) in sig.constraints.iter() {
+            let name = match sig.param_names.iter().find(|(_, var)| var == tvar) {
+                Some((name, _)) => name.clone(),
+                None => (tvar.0..)
+                    .map(|ind| syn::Ident::new(&format!("{}", TVar(ind)), span))
+                    .find(|name| !sig.param_names.iter().any(|(other, _)| other == name))
+                    .unwrap(),
+            };
+            names.push((*tvar, name));
+        }
+        let rename = names
+            .iter()
+            .map(|(tvar, name)| (*tvar, RustType::CAlias(name.clone())))
+            .collect();
+        for ty in sig.types_mut() {
+            *ty = rename_vars(ty, &rename)
+        }
+        let lifetimes = sig.lifetimes.into_iter().map(|(lt, bounds)| {
+            syn::GenericParam::Lifetime(syn::LifetimeDef {
+                attrs: vec![],
+                lifetime: lt.into(),
+                colon_token: (!bounds.is_empty()).then(Default::default),
+                bounds: bounds.into_iter().map(syn::Lifetime::from).collect(),
+            })
+        });
+        let params = sig
+            .constraints
+            .into_iter()
+            .zip(names)
+            .map(|((_, bounds), (_, name))| {
+                syn::GenericParam::Type(syn::TypeParam {
+                    attrs: vec![],
+                    ident: name,
+                    colon_token: (!bounds.is_empty()).then(Default::default),
+                    bounds: bounds
+                        .into_iter()
+                        .map(Into::<syn::TypeParamBound>::into)
+                        .collect(),
+                    eq_token: None,
+                    default: None,
+                })
+            });
+        let params: Punctuated<_, _> = lifetimes.chain(params).collect();
+        let where_clause = (!sig.predicates.is_empty()).then(|| syn::WhereClause {
+            where_token: Default::default(),
+            predicates: sig
+                .predicates
+                .into_iter()
+                .map(syn::WherePredicate::from)
+                .collect(),
+        });
+        let args = sig.args.into_iter().map(|(pat, ty)| {
+            syn::FnArg::Typed(syn::PatType {
+                attrs: vec![],
+                pat: Box::new(pat.into()),
+                colon_token: Default::default(),
+                ty: Box::new(ty.into()),
+            })
+        });
+        syn::Signature {
+            constness: sig.qualifiers.constness.then(Default::default),
+            asyncness: sig.qualifiers.asyncness.then(Default::default),
+            unsafety: sig.qualifiers.unsafety.then(Default::default),
+            abi: sig.qualifiers.abi.into(),
+            fn_token: Default::default(),
+            ident: syn::Ident::new(&sig.name, span),
+            generics: syn::Generics {
+                lt_token: (!params.is_empty()).then(Default::default),
+                gt_token: (!params.is_empty()).then(Default::default),
+                params,
+                where_clause,
+            },
+            paren_token: Default::default(),
+            inputs: sig
+                .receiver
+                .map(|v| v.into())
+                .into_iter()
+                .chain(args)
+                .collect(),
+            variadic: sig.variadic.then(|| syn::Variadic {
+                attrs: vec![],
+                dots: Default::default(),
+            }),
+            output: match sig.out_ty {
+                None => syn::ReturnType::Default,
+                Some(ty) => syn::ReturnType::Type(Default::default(), Box::new(ty.into())),
+            },
+        }
+    }
+}
+
+/// Builds a private function with the signature and a `todo!()` body
+impl From<RustTypeSignature> for syn::ItemFn {
+    fn from(sig: RustTypeSignature) -> Self {
+        syn::ItemFn {
+            attrs: vec![],
+            vis: syn::Visibility::Inherited,
+            sig: sig.into(),
+            block: Box::new(syn::parse_str("{ todo!() }").unwrap()),
+        }
+    }
+}
+
+/// Names brought into scope in a module by `use` declarations, and traits declared in it
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RustScope {
+    /// `use a::b::C as D;` maps `D` to `a::b::C`, as written
+    #[serde(with = "crate::serialize::ident_map")]
+    pub imports: HashMap<syn::Ident, RustPath>,
+    /// modules glob imported with `use a::b::*;`, as written
+    pub globs: Vec<RustPath>,
+    /// traits declared in the module, so that paths to them can be qualified like type paths
+    #[serde(default, with = "crate::serialize::ident_set")]
+    pub traits: HashSet<syn::Ident>,
+}
+
+impl RustScope {
+    fn add_use(&mut self, prefix: &mut Vec<syn::Ident>, tree: &syn::UseTree) {
+        match tree {
+            syn::UseTree::Path(syn::UsePath { ident, tree, .. }) => {
+                prefix.push(ident.clone());
+                self.add_use(prefix, tree);
+                prefix.pop();
+            }
+            syn::UseTree::Name(syn::UseName { ident }) if ident == "self" => {
+                if let Some(name) = prefix.last() {
+                    self.imports.insert(name.clone(), RustPath(prefix.clone()));
+                }
+            }
+            syn::UseTree::Name(syn::UseName { ident }) => {
+                let path = prefix.iter().chain([ident]).cloned().collect();
+                self.imports.insert(ident.clone(), RustPath(path));
+            }
+            syn::UseTree::Rename(syn::UseRename { ident, rename, .. }) => {
+                let path = match ident == "self" {
+                    true => prefix.clone(),
+                    false => prefix.iter().chain([ident]).cloned().collect(),
+                };
+                self.imports.insert(rename.clone(), RustPath(path));
+            }
+            syn::UseTree::Glob(_) => self.globs.push(RustPath(prefix.clone())),
+            syn::UseTree::Group(syn::UseGroup { items, .. }) => {
+                for tree in items.iter() {
+                    self.add_use(prefix, tree)
+                }
+            }
+        }
+    }
+}
+
+/// Returns true if `path` is the key of a declaration of the type context, or names a trait
+/// declared in one of its modules
+fn is_declared(path: &RustPath, ctxt: &ProgramTypeContext) -> bool {
+    ctxt.0.contains_key(path)
+        || ctxt.1.contains_key(path)
+        || ctxt.2.contains_key(path)
+        || ctxt.3.contains_key(path)
+        || path.segments().last().is_some_and(|name| {
+            ctxt.4
+                .get(&path.parent())
+                .is_some_and(|scope| scope.traits.contains(name))
+        })
+}
+
+/// Follows re-exports until `path` names a declaration, if possible
+fn canonical_path(
+    path: RustPath,
+    ctxt: &ProgramTypeContext,
+    seen: &mut HashSet<(RustPath, RustPath)>,
+) -> Option<RustPath> {
+    if is_declared(&path, ctxt) || path.segments().is_empty() {
+        return Some(path);
+    }
+    let module = path.parent();
+    match ctxt.4.contains_key(&module) {
+        true => qualify_checked(
+            &path.segments()[module.segments().len()..],
+            &module,
+            ctxt,
+            seen,
+        )
+        .or(Some(path)),
+        false => Some(path),
+    }
+}
+
+fn qualify_checked(
+    path: &[syn::Ident],
+    module: &RustPath,
+    ctxt: &ProgramTypeContext,
+    seen: &mut HashSet<(RustPath, RustPath)>,
+) -> Option<RustPath> {
+    let (first, rest) = path.split_first()?;
+    // guards against cyclic imports
+    if !seen.insert((module.clone(), RustPath(path.to_vec()))) {
+        return None;
+    }
+    if first == "crate" {
+        return canonical_path(RustPath(rest.to_vec()), ctxt, seen);
+    }
+    if first == "self" {
+        return canonical_path(module.join(rest), ctxt, seen);
+    }
+    if first == "super" {
+        let parent = module.parent();
+        return match rest.first() {
+            Some(next) if next == "super" => qualify_checked(rest, &parent, ctxt, seen),
+            _ => canonical_path(parent.join(rest), ctxt, seen),
+        };
+    }
+    let local = module.join(path);
+    if is_declared(&local, ctxt)
+        || ctxt
+            .4
+            .contains_key(&module.join(std::slice::from_ref(first)))
+    {
+        return canonical_path(local, ctxt, seen);
+    }
+    let scope = ctxt.4.get(module)?;
+    if let Some(import) = scope.imports.get(first) {
+        let imported = import.join(rest);
+        return qualify_checked(imported.segments(), module, ctxt, seen).or(Some(imported));
+    }
+    scope.globs.iter().find_map(|glob| {
+        let candidate = qualify_checked(glob.join(path).segments(), module, ctxt, seen)?;
+        is_declared(&candidate, ctxt).then_some(candidate)
+    })
+}
+
+/// Returns the fully qualified path that `path` refers to when written in `module`, following
+/// `use` imports and `mod` nesting. Paths that do not lead to a declaration of the crate (e.g
+/// `std::vec::Vec`) are returned unchanged
+pub fn qualify_path(path: &RustPath, module: &RustPath, ctxt: &ProgramTypeContext) -> RustPath {
+    let mut seen = HashSet::new();
+    qualify_checked(path.segments(), module, ctxt, &mut seen)
+        .filter(|path| is_declared(path, ctxt))
+        .unwrap_or_else(|| path.clone())
+}
+
+/// Returns the path of the function called as `path` in `module`, as named by
+/// `TypeMapCollector` without its leading `crate`: associated functions are qualified by their
+/// type, e.g `Circle::new` gives `shapes::Circle::new`, other paths by `qualify_path`'s rules
+pub fn qualify_fn_path(path: &RustPath, module: &RustPath, ctxt: &ProgramTypeContext) -> RustPath {
+    let Some((name, prefix)) = path.segments().split_last() else {
+        return path.clone();
+    };
+    if !prefix.is_empty() {
+        let ty = qualify_path(&RustPath(prefix.to_vec()), module, ctxt);
+        if is_declared(&ty, ctxt) {
+            return ty.join(std::slice::from_ref(name));
+        }
+    }
+    let mut seen = HashSet::new();
+    qualify_checked(path.segments(), module, ctxt, &mut seen)
+        .unwrap_or_else(|| module.join(path.segments()))
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CTypeContextCollector {
+    aliases: HashMap<RustPath, RustType>,
+    structs: HashMap<RustPath, RustStruct>,
+    enums: HashMap<RustPath, RustEnum>,
+    unions: HashMap<RustPath, RustUnion>,
+    modules: HashMap<RustPath, RustScope>,
+    /// module of the items currently being visited
+    module: RustPath,
+    /// declarations that could not be represented, and were skipped
+    errors: Vec<Error>,
+}
+
+impl CTypeContextCollector {
+    /// Sets the module that the items visited next are declared in, e.g `a::b` when visiting
+    /// the file `src/a/b.rs`
+    pub fn set_module(&mut self, module: RustPath) {
+        self.module = module;
+    }
+
+    fn key(&self, ident: &syn::Ident) -> RustPath {
+        self.module.join(std::slice::from_ref(ident))
+    }
+
+    /// Returns the type context, with the types of every declaration qualified by the module
+    /// it was declared in
+    pub fn to_type_context(mut self) -> ProgramTypeContext {
+        self.modules.entry(self.module.clone()).or_default();
+        let mut ctxt = (
+            self.aliases,
+            self.structs,
+            self.enums,
+            self.unions,
+            self.modules,
+        );
+        let reference = ctxt.clone();
+        for (key, ty) in ctxt.0.iter_mut() {
+            ty.qualify(&key.parent(), &reference)
+        }
+        for (key, st) in ctxt.1.iter_mut() {
+            for ty in st.fields.types_mut() {
+                ty.qualify(&key.parent(), &reference)
+            }
+        }
+        for (key, en) in ctxt.2.iter_mut() {
+            for ty in en.variants.iter_mut().flat_map(|v| v.fields.types_mut()) {
+                ty.qualify(&key.parent(), &reference)
+            }
+        }
+        for (key, un) in ctxt.3.iter_mut() {
+            for ty in un.fields.types_mut() {
+                ty.qualify(&key.parent(), &reference)
+            }
         }
+        ctxt
+    }
+
+    /// Returns the errors for every declaration that was skipped during collection
+    pub fn errors(&self) -> &Vec<Error> {
+        &self.errors
     }
 }
 
-impl std::fmt::Display for RustTypeConstraint {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            RustTypeConstraint::Index(ind_ty, out_ty) => write!(f, "Index<{},{}>", ind_ty, out_ty),
-            RustTypeConstraint::IndexMut(ind_ty, out_ty) => {
-                write!(f, "IndexMut<{},{}>", ind_ty, out_ty)
+impl<'ast> syn::visit::Visit<'ast> for CTypeContextCollector {
+    fn visit_item_mod(&mut self, i: &'ast syn::ItemMod) {
+        let parent = self.module.clone();
+        self.modules.entry(parent.clone()).or_default();
+        self.module = self.key(&i.ident);
+        self.modules.entry(self.module.clone()).or_default();
+        syn::visit::visit_item_mod(self, i);
+        self.module = parent;
+    }
+
+    fn visit_item_use(&mut self, i: &'ast syn::ItemUse) {
+        let scope = self.modules.entry(self.module.clone()).or_default();
+        scope.add_use(&mut vec![], &i.tree)
+    }
+
+    fn visit_item_type(&mut self, i: &'ast syn::ItemType) {
+        match RustType::try_from(&*i.ty) {
+            Ok(typ) => {
+                self.aliases.insert(self.key(&i.ident), typ);
             }
+            Err(err) => self.errors.push(err),
         }
     }
-}
 
-#[derive(Clone)]
-pub struct RustTypeSignature {
-    name: String,
-    constraints: Vec<(TVar, Vec<RustTypeConstraint>)>,
-    args: Vec<(String, RustType)>,
-    out_ty: Option<RustType>,
-}
+    fn visit_item_struct(&mut self, i: &'ast syn::ItemStruct) {
+        match RustStruct::try_from(i) {
+            Ok(st) => {
+                self.structs.insert(self.key(&i.ident), st);
+            }
+            Err(err) => self.errors.push(err),
+        }
+    }
 
-impl RustTypeSignature {
-    pub fn constraints(&self) -> &Vec<(TVar, Vec<RustTypeConstraint>)> {
-        &self.constraints
+    fn visit_item_enum(&mut self, i: &'ast syn::ItemEnum) {
+        match RustEnum::try_from(i) {
+            Ok(en) => {
+                self.enums.insert(self.key(&i.ident), en);
+            }
+            Err(err) => self.errors.push(err),
+        }
     }
 
-    pub fn args(&self) -> &Vec<(String, RustType)> {
-        &self.args
+    fn visit_item_union(&mut self, i: &'ast syn::ItemUnion) {
+        match RustUnion::try_from(i) {
+            Ok(un) => {
+                self.unions.insert(self.key(&i.ident), un);
+            }
+            Err(err) => self.errors.push(err),
+        }
+    }
+
+    fn visit_item_trait(&mut self, i: &'ast syn::ItemTrait) {
+        let scope = self.modules.entry(self.module.clone()).or_default();
+        scope.traits.insert(i.ident.clone());
     }
 }
 
-impl From<syn::Signature> for RustTypeSignature {
-    fn from(sig: syn::Signature) -> Self {
-        let name = sig.ident.to_string();
-        let constraints = sig
-            .generics
-            .type_params()
-            .into_iter()
-            .map(|param| {
-                let tvar = param.ident.to_string().into();
-                let bounds = param
-                    .bounds
-                    .clone()
-                    .into_pairs()
-                    .map(|v| v.into_value())
-                    .map(|v| v.into())
-                    .collect();
-                (tvar, bounds)
-            })
-            .collect::<Vec<_>>();
-        let args = sig
-            .inputs
-            .into_pairs()
-            .map(|v| match v.into_value() {
-                syn::FnArg::Typed(syn::PatType {
-                    pat: box syn::Pat::Ident(syn::PatIdent { ident, .. }),
-                    ty: box ty,
-                    ..
-                }) => (ident.to_string(), ty.into()),
-                v => panic!("unsupported pattern {:?} in signature", v),
-            })
-            .collect();
-        let out_ty = match sig.output {
-            syn::ReturnType::Default => None,
-            syn::ReturnType::Type(_, box ty) => Some(ty.into()),
-        };
-        RustTypeSignature {
-            name,
-            constraints,
-            args,
-            out_ty,
-        }
+/// Name of the type an impl is written for, as used in the names of its methods
+fn impl_name(self_ty: &syn::Type) -> String {
+    match self_ty {
+        Type::Path(syn::TypePath { qself: None, path }) => RustPath::from(path).to_string(),
+        ty => RustType::try_from(ty)
+            .map(|ty| ty.to_string())
+            .unwrap_or_else(|_| ty.to_token_stream().to_string()),
     }
 }
 
-impl std::fmt::Display for RustTypeSignature {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "fn {}<", self.name)?;
+/// Collects the signatures of the free functions, impl methods and trait methods of a crate.
+/// Functions are named by their module-qualified path starting with `crate`, with methods
+/// qualified by their impl, e.g `crate::shapes::Circle::area` for an inherent method and
+/// `crate::shapes::<Circle as Shape>::area` for a method of a trait impl.
+#[derive(Default, Clone)]
+pub struct TypeMapCollector {
+    types: TypeMap,
+    /// file of the items currently being visited
+    file: std::path::PathBuf,
+    /// directory the files of the `mod` declarations visited next are looked up in
+    dir: std::path::PathBuf,
+    module: RustPath,
+    /// impl or trait of the methods currently being visited, along with its `Self` type
+    scope: Option<(String, Option<RustType>)>,
+    /// generics of the impl or trait of the methods currently being visited
+    generics: syn::Generics,
+    /// modules declared without a body, along with the files that may define them
+    external: Vec<(RustPath, [std::path::PathBuf; 2])>,
+    /// declarations of the crate, used to qualify the traits of impls
+    ctxt: ProgramTypeContext,
+    /// signatures that could not be represented, and were skipped
+    errors: Vec<Error>,
+}
 
-        {
-            let constraints = self.constraints.iter().map(|v| Some(v)).intersperse(None);
-            for constraint in constraints {
-                match constraint {
-                    None => write!(f, ", ")?,
-                    Some((tvar, constraints)) => {
-                        write!(f, "{}: ", tvar)?;
-                        let constraints = constraints
-                            .iter()
-                            .map(|v| format!("{}", v))
-                            .intersperse(" + ".into());
-                        for opt in constraints {
-                            write!(f, "{}", opt)?
-                        }
-                    }
-                }
+impl TypeMapCollector {
+    /// Sets the file that the items visited next are written in
+    pub fn set_file(&mut self, file: std::path::PathBuf) {
+        let dir = file
+            .parent()
+            .unwrap_or(std::path::Path::new(""))
+            .to_path_buf();
+        // `a.rs` defines its submodules in `a/`, unlike `mod.rs`, `lib.rs` and `main.rs`
+        self.dir = match file.file_stem().and_then(|stem| stem.to_str()) {
+            Some("mod" | "lib" | "main") | None => dir,
+            Some(stem) => dir.join(stem),
+        };
+        self.file = file;
+    }
+
+    /// Sets the module that the items visited next are declared in, see
+    /// `CTypeContextCollector::set_module`
+    pub fn set_module(&mut self, module: RustPath) {
+        self.module = module;
+    }
+
+    /// Sets the declarations of the crate, see `CTypeContextCollector::to_type_context`
+    pub fn set_type_context(&mut self, ctxt: ProgramTypeContext) {
+        self.ctxt = ctxt;
+    }
+
+    /// Visits the crate whose root file is `root`, e.g `src/lib.rs`, along with the files of
+    /// every module it declares
+    pub fn visit_crate<S: FileSystem>(
+        &mut self,
+        fs: &S,
+        root: &std::path::Path,
+    ) -> Result<(), crate::error::Error> {
+        // the files of the crate are found first, as an impl may refer to a trait of a module
+        // that has not been visited yet
+        let mut finder = TypeMapCollector::default();
+        let mut asts = vec![];
+        let mut files = vec![(RustPath::default(), root.to_path_buf())];
+        while let Some((module, file)) = files.pop() {
+            let src = fs
+                .read(&file)
+                .map_err(|err| format!("could not read {}: {:?}", file.display(), err))?;
+            let ast = syn::parse_file(&src)
+                .map_err(|err| format!("could not parse {}: {}", file.display(), err))?;
+            finder.set_file(file.clone());
+            finder.set_module(module.clone());
+            syn::visit::Visit::visit_file(&mut finder, &ast);
+            for (module, candidates) in std::mem::take(&mut finder.external) {
+                let file = candidates
+                    .into_iter()
+                    .find(|file| fs.exists(file).unwrap_or(false))
+                    .ok_or_else(|| format!("could not find the file of module {}", module))?;
+                files.push((module, file))
             }
+            asts.push((module, file, ast));
         }
-        write!(f, ">(")?;
-        {
-            let mut args = self.args.iter();
-            let mut next = args.next();
-            while next.is_some() {
-                let (name, ty) = next.unwrap();
-                write!(f, "{}: {}", name, ty)?;
-                next = args.next();
-                if next.is_some() {
-                    write!(f, ",")?;
-                }
-            }
+
+        let mut declarations = CTypeContextCollector::default();
+        for (module, _, ast) in asts.iter() {
+            declarations.set_module(module.clone());
+            syn::visit::Visit::visit_file(&mut declarations, ast);
         }
-        write!(f, ")")?;
-        match &self.out_ty {
-            None => (),
-            Some(ty) => writeln!(f, " -> {}", ty)?,
+        self.set_type_context(declarations.to_type_context());
+        for (module, file, ast) in asts {
+            self.set_file(file);
+            self.set_module(module);
+            syn::visit::Visit::visit_file(self, &ast);
         }
-
+        self.external.clear();
         Ok(())
     }
-}
 
-#[derive(Debug, Default, Clone)]
-pub struct CTypeContextCollector {
-    aliases: HashMap<syn::Ident, RustType>,
-    structs: HashMap<syn::Ident, RustStruct>,
-}
+    pub fn to_type_map(self) -> TypeMap {
+        self.types
+    }
 
-impl CTypeContextCollector {
-    pub fn to_type_context(self) -> ProgramTypeContext {
-        (self.aliases, self.structs)
+    /// Returns the errors for every signature that was skipped during collection
+    pub fn errors(&self) -> &Vec<Error> {
+        &self.errors
+    }
+
+    fn insert(&mut self, sig: &syn::Signature) {
+        // the parameters of the impl come first, so that they are numbered before the
+        // parameters of the method
+        let mut merged = sig.clone();
+        merged.generics.params = self
+            .generics
+            .params
+            .iter()
+            .chain(sig.generics.params.iter())
+            .cloned()
+            .collect();
+        let predicates = self
+            .generics
+            .where_clause
+            .iter()
+            .chain(sig.generics.where_clause.iter())
+            .flat_map(|clause| clause.predicates.iter())
+            .cloned()
+            .collect::<Punctuated<_, _>>();
+        merged.generics.where_clause = (!predicates.is_empty()).then(|| syn::WhereClause {
+            where_token: Default::default(),
+            predicates,
+        });
+        let mut typ = match RustTypeSignature::try_from(&merged) {
+            Ok(typ) => typ,
+            Err(err) => return self.errors.push(err),
+        };
+        let mut name = std::iter::once("crate".to_string())
+            .chain(self.module.segments().iter().map(|v| v.to_string()))
+            .collect::<Vec<_>>();
+        if let Some((scope, self_ty)) = &self.scope {
+            name.push(scope.clone());
+            if let Some(self_ty) = self_ty {
+                let params = typ.param_names.clone();
+                for ty in typ.types_mut() {
+                    crate::traits::replace_self(ty, self_ty);
+                    abstract_tvars(ty, &params)
+                }
+            }
+        }
+        name.push(sig.ident.to_string());
+        let line = sig.ident.span().start().line as u32;
+        let loc = crate::location::RawLoc::new(self.file.clone(), vec![line]);
+        self.types.insert((loc, name.join("::")).into(), typ);
     }
 }
 
-impl<'ast> syn::visit::Visit<'ast> for CTypeContextCollector {
-    fn visit_item_type(&mut self, i: &'ast syn::ItemType) {
-        let typ: RustType = (&*i.ty).clone().into();
+impl<'ast> syn::visit::Visit<'ast> for TypeMapCollector {
+    fn visit_item_mod(&mut self, i: &'ast syn::ItemMod) {
+        let module = self.module.join(std::slice::from_ref(&i.ident));
+        let name = i.ident.to_string();
+        if i.content.is_none() {
+            let candidates = [
+                self.dir.join(format!("{}.rs", name)),
+                self.dir.join(&name).join("mod.rs"),
+            ];
+            self.external.push((module, candidates));
+            return;
+        }
+        let (parent, dir) = (self.module.clone(), self.dir.clone());
+        self.module = module;
+        self.dir = dir.join(name);
+        syn::visit::visit_item_mod(self, i);
+        (self.module, self.dir) = (parent, dir);
+    }
 
-        self.aliases.insert(i.ident.clone(), typ);
+    fn visit_item_fn(&mut self, i: &'ast syn::ItemFn) {
+        // functions nested in a body are not reachable by name, so the body is not visited
+        self.insert(&i.sig)
     }
 
-    fn visit_item_struct(&mut self, i: &'ast syn::ItemStruct) {
-        self.structs.insert(i.ident.clone(), i.clone().into());
+    fn visit_item_impl(&mut self, i: &'ast syn::ItemImpl) {
+        let self_name = impl_name(&i.self_ty);
+        let scope = match &i.trait_ {
+            Some((_, path, _)) => {
+                let path = qualify_path(&path.into(), &self.module, &self.ctxt);
+                match is_declared(&path, &self.ctxt) {
+                    true => format!("<{} as crate::{}>", self_name, path),
+                    false => format!("<{} as {}>", self_name, path),
+                }
+            }
+            None => self_name,
+        };
+        let self_ty = RustType::try_from(&*i.self_ty).ok();
+        self.scope = Some((scope, self_ty));
+        self.generics = i.generics.clone();
+        syn::visit::visit_item_impl(self, i);
+        self.scope = None;
+        self.generics = Default::default();
     }
 
-    fn visit_item_enum(&mut self, i: &'ast syn::ItemEnum) {
-        panic!(
-            "found use of unsupported enum {:?} declaration",
-            i.to_token_stream().to_string()
-        )
+    fn visit_item_trait(&mut self, i: &'ast syn::ItemTrait) {
+        self.scope = Some((i.ident.to_string(), None));
+        self.generics = i.generics.clone();
+        syn::visit::visit_item_trait(self, i);
+        self.scope = None;
+        self.generics = Default::default();
+    }
+
+    fn visit_impl_item_method(&mut self, i: &'ast syn::ImplItemMethod) {
+        self.insert(&i.sig)
+    }
+
+    fn visit_trait_item_method(&mut self, i: &'ast syn::TraitItemMethod) {
+        self.insert(&i.sig)
     }
 }
 
 fn check_recursive(
-    checking: &syn::Ident,
-    current: syn::Ident,
-    mut path: HashSet<syn::Ident>,
-    usage_map: &HashMap<syn::Ident, HashSet<syn::Ident>>,
+    checking: &RustPath,
+    current: RustPath,
+    mut path: HashSet<RustPath>,
+    usage_map: &HashMap<RustPath, HashSet<RustPath>>,
 ) -> bool {
     // hit a recursion, exit
     if path.contains(&current) {
@@ -951,9 +3817,9 @@ fn check_recursive(
     }
 }
 
-pub fn normalize_type_context(ctxt: &mut ProgramTypeContext) -> HashSet<syn::Ident> {
+pub fn normalize_type_context(ctxt: &mut ProgramTypeContext) -> HashSet<RustPath> {
     let mut usage_map = HashMap::new();
-    let ref_ctx = (ctxt.0.clone(), ctxt.1.clone());
+    let ref_ctx = ctxt.clone();
     for (_name, st) in ctxt.0.iter_mut() {
         st.resolve(&ref_ctx);
     }
@@ -961,6 +3827,14 @@ pub fn normalize_type_context(ctxt: &mut ProgramTypeContext) -> HashSet<syn::Ide
         st.resolve(&ref_ctx);
         usage_map.insert(name.clone(), st.uses());
     }
+    for (name, en) in ctxt.2.iter_mut() {
+        en.resolve(&ref_ctx);
+        usage_map.insert(name.clone(), en.uses());
+    }
+    for (name, un) in ctxt.3.iter_mut() {
+        un.resolve(&ref_ctx);
+        usage_map.insert(name.clone(), un.uses());
+    }
 
     let mut recursive = HashSet::new();
 
@@ -982,15 +3856,684 @@ pub fn normalize_type_context(ctxt: &mut ProgramTypeContext) -> HashSet<syn::Ide
 impl UnifyValue for RustType {
     type Error = Error;
 
+    /// Unifies two values, resolving any type variables they share
     fn unify_values(value1: &Self, value2: &Self) -> Result<Self, Self::Error> {
-        match (value1, value2) {
-            (t1, t2) if t1 == t2 => Ok(t1.clone()),
-            (RustType::TVar(t1), RustType::TVar(t2)) if t1 == t2 => Ok(RustType::TVar(*t1)),
-            (RustType::Pointer(box x), RustType::Pointer(box y)) => {
-                let contents = Self::unify_values(x, y)?;
-                Ok(RustType::Pointer(Box::new(contents)))
+        crate::unify::unify(value1, value2).map(|(ty, _)| ty)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pprint_ast, CHRusty_parse};
+
+    #[test]
+    fn test_primitive_types_round_trip_through_syn() {
+        for name in [
+            "bool", "char", "str", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32",
+            "u64", "u128", "usize", "f32", "f64",
+        ] {
+            let ty: RustType = CHRusty_parse!(name as syn::Type).into();
+            assert!(ty.is_primitive(), "{} was not recognised as a primitive", name);
+            assert_eq!(format!("{}", ty), name);
+            let syn_ty: syn::Type = ty.into();
+            assert_eq!(pprint_ast!(syn_ty), name);
+        }
+    }
+
+    #[test]
+    fn test_primitive_types_parse_from_display() {
+        for ty in [RustType::Bool, RustType::I128, RustType::U16, RustType::F64, RustType::Str] {
+            let txt = format!("{}", ty);
+            let (rest, parsed) = crate::parser::rust_type(&txt).unwrap();
+            assert!(rest.is_empty());
+            assert_eq!(parsed, ty);
+        }
+    }
+
+    #[test]
+    fn test_pointer_mutability_is_preserved() {
+        for (src, display) in [
+            ("*const i32", "const_ptr_i32"),
+            ("*mut i32", "mut_ptr_i32"),
+            ("*mut *const *mut libc::c_int", "mut_ptr_const_ptr_mut_ptr_c_int"),
+        ] {
+            let ty: RustType = CHRusty_parse!(src as syn::Type).into();
+            assert_eq!(format!("{}", ty), display);
+            assert_eq!(crate::parser::rust_type(display).unwrap(), ("", ty.clone()));
+            let syn_ty: syn::Type = ty.into();
+            let expected: syn::Type = CHRusty_parse!(src as syn::Type);
+            assert_eq!(pprint_ast!(syn_ty), pprint_ast!(expected));
+        }
+    }
+
+    #[test]
+    fn test_pointers_of_different_mutability_do_not_unify() {
+        let const_ptr = RustType::Pointer(RustMutability::Immutable, Box::new(RustType::I32));
+        let mut_ptr = RustType::Pointer(RustMutability::Mutable, Box::new(RustType::I32));
+        assert!(RustType::unify_values(&const_ptr, &mut_ptr).is_err());
+        assert_eq!(RustType::unify_values(&mut_ptr, &mut_ptr).unwrap(), mut_ptr);
+    }
+
+    #[test]
+    fn test_generic_nominal_types_round_trip_through_syn() {
+        for (src, display) in [
+            ("Box<i32>", "adt(Box, [i32])"),
+            ("std::rc::Rc<Node>", "adt(std::rc::Rc, [Node])"),
+            (
+                "HashMap<u64, Vec<*mut c_void>>",
+                "adt(HashMap, [u64, vec(mut_ptr_c_void)])",
+            ),
+            ("crate::list::Node", "adt(crate::list::Node, [])"),
+            ("Cow<'a, str>", "adt(Cow, ['a, str])"),
+            ("fmt::Formatter<'_>", "adt(fmt::Formatter, ['_])"),
+        ] {
+            let ty: RustType = CHRusty_parse!(src as syn::Type).into();
+            assert_eq!(format!("{}", ty), display);
+            assert_eq!(crate::parser::rust_type(display).unwrap(), ("", ty.clone()));
+            let syn_ty: syn::Type = ty.clone().into();
+            let round_trip: RustType = syn_ty.into();
+            assert_eq!(round_trip, ty);
+        }
+    }
+
+    #[test]
+    fn test_generic_nominal_types_unify_argument_wise() {
+        let ty: RustType = CHRusty_parse!("HashMap<usize, u8>" as syn::Type).into();
+        let other: RustType = CHRusty_parse!("HashMap<size_t, u8>" as syn::Type).into();
+        let unified = RustType::unify_values(&ty, &other).unwrap();
+        assert_eq!(unified, ty);
+
+        let different: RustType = CHRusty_parse!("BTreeMap<usize, u8>" as syn::Type).into();
+        assert!(RustType::unify_values(&ty, &different).is_err());
+    }
+
+    #[test]
+    fn test_tuple_and_slice_types_round_trip() {
+        for (src, display) in [
+            ("(i32, *mut u8)", "tuple(i32, mut_ptr_u8)"),
+            ("(usize,)", "tuple(usize)"),
+            ("&[f64]", "ref_immutable_slice(f64)"),
+            ("(bool, (char, u16))", "tuple(bool, tuple(char, u16))"),
+        ] {
+            let ty: RustType = CHRusty_parse!(src as syn::Type).into();
+            assert_eq!(format!("{}", ty), display);
+            let syn_ty: syn::Type = ty.clone().into();
+            let expected: syn::Type = CHRusty_parse!(src as syn::Type);
+            assert_eq!(pprint_ast!(syn_ty), pprint_ast!(expected));
+        }
+
+        let ty = RustType::Tuple(vec![
+            RustType::Slice(Box::new(RustType::U8)),
+            RustType::Pointer(RustMutability::Mutable, Box::new(RustType::I32)),
+        ]);
+        let txt = format!("{}", ty);
+        assert_eq!(crate::parser::rust_type(&txt).unwrap(), ("", ty));
+    }
+
+    #[test]
+    fn test_tuples_unify_element_wise() {
+        let t1 = RustType::Tuple(vec![RustType::SizeT, RustType::Bool]);
+        let t2 = RustType::Tuple(vec![RustType::Usize, RustType::Bool]);
+        assert_eq!(
+            RustType::unify_values(&t1, &t2).unwrap(),
+            RustType::Tuple(vec![RustType::Usize, RustType::Bool])
+        );
+        let t3 = RustType::Tuple(vec![RustType::Usize]);
+        assert!(RustType::unify_values(&t1, &t3).is_err());
+    }
+
+    #[test]
+    fn test_reference_lifetimes_round_trip() {
+        for (src, display) in [
+            ("&'a mut i32", "ref<'a>_mutable_i32"),
+            ("&'static str", "ref<'static>_immutable_str"),
+            ("&[u8]", "ref_immutable_slice(u8)"),
+            ("&'a_b &'c u8", "ref<'a_b>_immutable_ref<'c>_immutable_u8"),
+        ] {
+            let ty: RustType = CHRusty_parse!(src as syn::Type).into();
+            assert_eq!(format!("{}", ty), display);
+            assert_eq!(crate::parser::rust_type(display).unwrap(), ("", ty.clone()));
+            let syn_ty: syn::Type = ty.into();
+            let expected: syn::Type = CHRusty_parse!(src as syn::Type);
+            assert_eq!(pprint_ast!(syn_ty), pprint_ast!(expected));
+        }
+    }
+
+    #[test]
+    fn test_signature_lifetime_generics() {
+        let sig: RustTypeSignature =
+            CHRusty_parse!("fn longest<'a, 'b: 'a>(x: &'a str, y: &'b str) -> &'a str" as syn::Signature)
+                .into();
+        assert_eq!(
+            sig.lifetimes(),
+            &vec![
+                (RustLifetime("a".into()), vec![]),
+                (RustLifetime("b".into()), vec![RustLifetime("a".into())])
+            ]
+        );
+        assert_eq!(
+            format!("{}", sig),
+            "fn longest<'a, 'b: 'a>(x: ref<'a>_immutable_str,y: ref<'b>_immutable_str) -> ref<'a>_immutable_str"
+        );
+    }
+
+    #[test]
+    fn test_method_signatures_with_patterns_and_where_clauses() {
+        for (src, display) in [
+            (
+                "const unsafe fn get<'b>(&'b mut self, (i, _): (usize, u8)) -> &'b T0",
+                "const unsafe fn get<'b>(&'b mut self,(i, _): tuple(usize, u8)) -> ref<'b>_immutable_alias(T0)",
+            ),
+            (
+                "async fn run(mut self: Box<Self>, Point { x: ref mut a, .. }: Point)",
+                "async fn run<>(mut self: adt(Box, [Self]),Point { x: ref mut a, .. }: Point)",
+            ),
+            (
+                "extern \"C\" fn walk<'a, T0>(self, [first, ..]: &'a [T0]) where T0: Index<usize, Output = u8>, 'a: 'static, Vec<T0>: IndexMut<usize, Output = T0>",
+                "extern \"C\" fn walk<'a: 'static, T0: Index[usize] -> u8>(self,[first, ..]: ref<'a>_immutable_slice(T0)) where vec(T0): IndexMut[usize] -> T0",
+            ),
+            (
+                "unsafe extern \"C\" fn printf(fmt: *const c_char, ...) -> c_int",
+                "unsafe extern \"C\" fn printf<>(fmt: const_ptr_c_char,...) -> c_int",
+            ),
+        ] {
+            let sig: RustTypeSignature = CHRusty_parse!(src as syn::Signature).into();
+            assert_eq!(format!("{}", sig), display);
+        }
+
+        let sig: RustTypeSignature =
+            CHRusty_parse!("fn swap(&mut self, &mut (ref a, b): &mut (u8, u8))" as syn::Signature)
+                .into();
+        assert_eq!(
+            sig.receiver(),
+            Some(&RustReceiver::Reference(None, RustMutability::Mutable))
+        );
+        let bindings = sig.args()[0].0.bindings();
+        assert_eq!(
+            bindings.iter().map(|v| v.to_string()).collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+    }
+
+    #[test]
+    fn test_generic_parameters_are_numbered_by_position() {
+        for (src, display, emitted) in [
+            (
+                "fn foo<T: Clone>(x: T) -> T",
+                "fn foo<T0: Clone>(x: T0) -> T0",
+                "fn foo<T: Clone>(x: T) -> T",
+            ),
+            (
+                "fn g<K, V>(map: &HashMap<K, V>, key: K) -> Option<&V> where V: Default",
+                "fn g<T0: , T1: Default>(map: ref_immutable_adt(HashMap, [T0, T1]),key: T0) -> option(ref_immutable_T1)",
+                "fn g<K, V: Default>(map: &HashMap<K, V>, key: K) -> Option<&V>",
+            ),
+        ] {
+            let sig: RustTypeSignature = CHRusty_parse!(src as syn::Signature).into();
+            assert_eq!(format!("{}", sig), display);
+            let actual: syn::Signature = sig.into();
+            let expected: syn::Signature = CHRusty_parse!(emitted as syn::Signature);
+            assert_eq!(pprint_ast!(actual), pprint_ast!(expected));
+        }
+    }
+
+    #[test]
+    fn test_signatures_round_trip_through_syn() {
+        for src in [
+            "fn main()",
+            "fn longest<'a, 'b: 'a>(x: &'a str, y: &'b str) -> &'a str",
+            "const unsafe fn get<'b>(&'b mut self, (i, _): (usize, u8)) -> &'b T0",
+            "async fn run(mut self: Box<Self>, Point { x: ref mut a, .. }: Point) -> ()",
+            "extern \"C\" fn walk<T0: IndexMut<usize, Output = u8> + Index<usize, Output = u8>>(self, [first, ..]: &[T0], (x,): (i32,)) where Vec<T0>: Index<usize, Output = T0>",
+            "fn map<T0, T1: Index<T0, Output = T0>>(f: T1, S(a, _): S) -> Option<T0>",
+            "unsafe extern \"C\" fn printf(fmt: *const libc::c_char, ...) -> libc::c_int",
+        ] {
+            let expected: syn::Signature = CHRusty_parse!(src as syn::Signature);
+            let sig: RustTypeSignature = expected.clone().into();
+            let name = sig.name().to_string();
+            let actual: syn::Signature = sig.clone().into();
+            assert_eq!(pprint_ast!(actual), pprint_ast!(expected));
+
+            let stub: syn::ItemFn = sig.into();
+            assert_eq!(stub.sig.ident, name);
+            assert_eq!(pprint_ast!(stub.block), "{ todo ! () }");
+        }
+    }
+
+    #[test]
+    fn test_trait_bounds_round_trip_through_syn() {
+        for (src, display) in [
+            ("Copy", "Copy"),
+            ("std::clone::Clone", "std::clone::Clone"),
+            ("Fn(i32, &str) -> bool", "Fn(i32,ref_immutable_str) -> bool"),
+            ("FnOnce()", "FnOnce() -> ()"),
+            // `T0` is not declared, so it is an alias rather than a type variable
+            ("Deref<Target = T0>", "Deref<Target=alias(T0)>"),
+            (
+                "Iterator<Item = (usize, u8)>",
+                "Iterator<Item=tuple(usize, u8)>",
+            ),
+            ("From<Vec<u8>>", "From<vec(u8)>"),
+            ("Index<usize, Output = u8>", "Index[usize] -> u8"),
+            ("?Sized", "?Sized"),
+            ("'static", "'static"),
+        ] {
+            let bound: syn::TypeParamBound = CHRusty_parse!(src as syn::TypeParamBound);
+            let constraint = RustTypeConstraint::try_from(&bound).unwrap();
+            assert_eq!(format!("{}", constraint), display);
+            let actual: syn::TypeParamBound = constraint.into();
+            assert_eq!(pprint_ast!(actual), pprint_ast!(bound));
+        }
+
+        let bound: syn::TypeParamBound =
+            CHRusty_parse!("for<'a> Fn(&'a u8)" as syn::TypeParamBound);
+        assert!(RustTypeConstraint::try_from(&bound).is_err());
+    }
+
+    #[test]
+    fn test_signatures_are_instantiated_and_generalised() {
+        let sig: RustTypeSignature = CHRusty_parse!(
+            "fn get<T0: Clone, T1>(xs: &[T0], f: T1) -> Option<T0> where T1: Fn(usize) -> T0"
+                as syn::Signature
+        )
+        .into();
+        assert_eq!(sig.free_vars(), vec![TVar(0), TVar(1)]);
+
+        // instantiating with fresh variables keeps the bounds, on the fresh variables
+        let mut next = 10;
+        let (inst, subst) = sig.instantiate(|| {
+            next += 1;
+            RustType::TVar(TVar(next))
+        });
+        assert_eq!(subst.get(&TVar(0)), Some(&RustType::TVar(TVar(11))));
+        assert_eq!(
+            format!("{}", inst),
+            "fn get<T11: Clone, T12: Fn(usize) -> T11>(xs: ref_immutable_slice(T11),f: T12) -> option(T11)"
+        );
+
+        // binding a parameter to a concrete type removes it
+        let concrete = sig.substitute(&[(TVar(0), RustType::U8)].into_iter().collect());
+        assert_eq!(
+            format!("{}", concrete),
+            "fn get<T1: Fn(usize) -> u8>(xs: ref_immutable_slice(u8),f: T1) -> option(u8)"
+        );
+
+        // inferred signatures are renumbered, and only keep bounds on their own variables
+        let inferred = RustTypeSignature::new(
+            "sum",
+            vec![(
+                RustPattern::try_from(&CHRusty_parse!("xs" as syn::Pat)).unwrap(),
+                RustType::Vec(Box::new(RustType::TVar(TVar(7)))),
+            )],
+            Some(RustType::TVar(TVar(7))),
+        );
+        let bound = |src: &str| {
+            let mut bound: RustTypeConstraint = CHRusty_parse!(src as syn::TypeParamBound).into();
+            for ty in bound.types_mut() {
+                abstract_tvars(ty, &[(syn::parse_str("T7").unwrap(), TVar(7))])
+            }
+            bound
+        };
+        let general = inferred.generalise(&[
+            (
+                TVar(7),
+                vec![bound("std::ops::Add<Output = T7>"), bound("Copy")],
+            ),
+            (TVar(3), vec![bound("Copy")]),
+        ]);
+        assert_eq!(
+            format!("{}", general),
+            "fn sum<T0: std::ops::Add<Output=T0> + Copy>(xs: vec(T0)) -> T0"
+        );
+        let stub: syn::Signature = general.into();
+        assert_eq!(
+            pprint_ast!(stub),
+            pprint_ast!(CHRusty_parse!(
+                "fn sum<T0: std::ops::Add<Output = T0> + Copy>(xs: Vec<T0>) -> T0"
+                    as syn::Signature
+            ))
+        );
+
+        let (abstracted, tvar) = concrete.abstract_over(&RustType::U8);
+        assert_eq!(tvar, TVar(2));
+        assert_eq!(
+            format!("{}", abstracted),
+            "fn get<T1: Fn(usize) -> T2, T2: >(xs: ref_immutable_slice(T2),f: T1) -> option(T2)"
+        );
+    }
+
+    #[test]
+    fn test_trait_objects_and_opaque_types_round_trip() {
+        for (src, display) in [
+            (
+                "Box<dyn Index<usize, Output = u8>>",
+                "adt(Box, [dyn(Index[usize] -> u8)])",
+            ),
+            (
+                "&mut dyn IndexMut<usize, Output = i32>",
+                "ref_mutable_dyn(IndexMut[usize] -> i32)",
+            ),
+            (
+                "impl Index<usize, Output = (usize, char)>",
+                "impl(Index[usize] -> tuple(usize, char))",
+            ),
+            ("Box<dyn Fn(i32) -> i32>", "adt(Box, [dyn(Fn(i32) -> i32)])"),
+            (
+                "&mut dyn FnMut(&str)",
+                "ref_mutable_dyn(FnMut(ref_immutable_str) -> ())",
+            ),
+            (
+                "impl Iterator<Item = (usize, char)>",
+                "impl(Iterator<Item=tuple(usize, char)>)",
+            ),
+            (
+                "Box<dyn std::error::Error + Send + 'static>",
+                "adt(Box, [dyn(std::error::Error + Send + 'static)])",
+            ),
+        ] {
+            let ty: RustType = CHRusty_parse!(src as syn::Type).into();
+            assert_eq!(format!("{}", ty), display);
+            let syn_ty: syn::Type = ty.into();
+            let expected: syn::Type = CHRusty_parse!(src as syn::Type);
+            assert_eq!(pprint_ast!(syn_ty), pprint_ast!(expected));
+        }
+    }
+
+    #[test]
+    fn test_fn_pointers_round_trip_through_syn() {
+        for (src, display) in [
+            ("fn(i32) -> i32", "fn_ptr(safe, rust, [i32], fixed, i32)"),
+            (
+                "unsafe extern \"C\" fn(libc::c_int, ...) -> libc::c_int",
+                "fn_ptr(unsafe, extern(\"C\"), [c_int], variadic, c_int)",
+            ),
+            (
+                "extern fn(*mut u8, usize)",
+                "fn_ptr(safe, extern, [mut_ptr_u8, usize], fixed, ())",
+            ),
+            (
+                "unsafe extern \"system\" fn() -> !",
+                "fn_ptr(unsafe, extern(\"system\"), [], fixed, never)",
+            ),
+        ] {
+            let ty: RustType = CHRusty_parse!(src as syn::Type).into();
+            assert_eq!(format!("{}", ty), display);
+            let syn_ty: syn::Type = ty.into();
+            let expected: syn::Type = CHRusty_parse!(src as syn::Type);
+            assert_eq!(pprint_ast!(syn_ty), pprint_ast!(expected));
+        }
+
+        let ty: syn::Type = CHRusty_parse!("for<'a> fn(&'a u8) -> &'a u8" as syn::Type);
+        assert!(RustType::try_from(&ty).is_err());
+
+        // only a verbatim `...` argument is read as the variadic of older C2Rust output
+        let with_verbatim = |tokens: proc_macro2::TokenStream| {
+            let mut ty: syn::TypeBareFn =
+                CHRusty_parse!("unsafe extern \"C\" fn(c_int)" as syn::TypeBareFn);
+            ty.inputs.push(syn::BareFnArg {
+                attrs: vec![],
+                name: None,
+                ty: Type::Verbatim(tokens),
+            });
+            RustType::try_from(&Type::BareFn(ty))
+        };
+        assert_eq!(
+            format!("{}", with_verbatim(quote::quote!(...)).unwrap()),
+            "fn_ptr(unsafe, extern(\"C\"), [c_int], variadic, ())"
+        );
+        assert!(matches!(
+            with_verbatim(quote::quote!(const N)),
+            Err(Error::Unsupported {
+                construct: "type",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_types_are_reported_with_their_span() {
+        let ty: syn::Type = CHRusty_parse!("[u8; N]" as syn::Type);
+        match RustType::try_from(&ty) {
+            Err(Error::Unsupported {
+                construct,
+                tokens,
+                span,
+            }) => {
+                assert_eq!(construct, "type");
+                assert_eq!(tokens, "[u8 ; N]");
+                assert_eq!(span.start().column, 0);
+            }
+            res => panic!("expected an unsupported construct error, got {:?}", res),
+        }
+
+        let ty: syn::Type = CHRusty_parse!("<T as Iterator>::Item" as syn::Type);
+        assert!(matches!(
+            RustType::try_from(&ty),
+            Err(Error::Unsupported {
+                construct: "type",
+                ..
+            })
+        ));
+
+        let sig: syn::Signature =
+            CHRusty_parse!("fn f(x @ Some(_): Option<i32>)" as syn::Signature);
+        assert!(matches!(
+            RustTypeSignature::try_from(&sig),
+            Err(Error::Unsupported {
+                construct: "pattern",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_collector_gathers_errors_instead_of_panicking() {
+        let file = syn::parse_file(
+            "
+            type Len = usize;
+            type Unsupported = [u8; LEN];
+            enum Colour { Red, Green }
+            struct Point { x: i32, y: i32 }
+            struct Buffer<const N: usize> { data: [u8; N] }
+            ",
+        )
+        .unwrap();
+        let mut collector = CTypeContextCollector::default();
+        syn::visit::Visit::visit_file(&mut collector, &file);
+
+        let errors = collector
+            .errors()
+            .iter()
+            .map(|err| match err {
+                Error::Unsupported { construct, .. } => *construct,
+                err => panic!("unexpected error {:?}", err),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(errors, vec!["type", "generic parameter"]);
+
+        let (aliases, structs, enums, _, _) = collector.to_type_context();
+        assert_eq!(aliases.len(), 1);
+        assert_eq!(structs.len(), 1);
+        assert_eq!(enums.len(), 1);
+    }
+
+    #[test]
+    fn test_struct_shapes_generics_and_attributes() {
+        let tuple: RustStruct = CHRusty_parse!(
+            "#[repr(C, align(8))] #[derive(Clone, Debug)] pub struct Pair<'a, T: Index<usize, Output = u8>>(pub &'a T, u8);"
+            as syn::ItemStruct
+        )
+        .into();
+        assert_eq!(tuple.vis(), &RustVisibility::Public);
+        assert_eq!(tuple.attrs().repr, vec!["C", "align(8)"]);
+        assert_eq!(tuple.attrs().derives.len(), 2);
+        assert_eq!(
+            format!("{}", tuple),
+            "pub struct Pair<'a, T0: Index[usize] -> u8>(pub ref<'a>_immutable_T0,u8);"
+        );
+        let field = syn::Member::Unnamed(0.into());
+        assert_eq!(
+            tuple.field_type(&field, &[RustType::Char]),
+            Some(CHRusty_parse!("&'a char" as syn::Type).into())
+        );
+
+        let unit: RustStruct =
+            CHRusty_parse!("pub(crate) struct Marker;" as syn::ItemStruct).into();
+        assert_eq!(unit.vis(), &RustVisibility::Crate);
+        assert_eq!(unit.fields(), &RustFields::Unit);
+    }
+
+    #[test]
+    fn test_enums_and_unions_are_collected() {
+        let file = syn::parse_file(
+            "
+            enum List<T> { Cons(T, Box<List<T>>), Nil = -1 }
+            union Bits { raw: u32, parts: Parts }
+            struct Parts { lo: u16, hi: u16, owner: *mut Bits }
+            enum Flag { On = 1, Off = FLAG_OFF }
+            ",
+        )
+        .unwrap();
+        let mut collector = CTypeContextCollector::default();
+        syn::visit::Visit::visit_file(&mut collector, &file);
+        assert!(collector.errors().is_empty());
+
+        let mut ctxt = collector.to_type_context();
+        let list = &ctxt.2[&(&syn::parse_str::<syn::Path>("List").unwrap()).into()];
+        assert_eq!(
+            format!("{}", list),
+            "enum List<T0> { Cons(T0,adt(Box, [adt(List, [T0])])),Nil = -1 }"
+        );
+        let flag = &ctxt.2[&(&syn::parse_str::<syn::Path>("Flag").unwrap()).into()];
+        assert_eq!(
+            flag.variants()[1].discriminant,
+            Some(RustDiscriminant::Unknown("FLAG_OFF".to_string()))
+        );
+        let recursive = normalize_type_context(&mut ctxt)
+            .into_iter()
+            .map(|v| v.to_string())
+            .collect::<HashSet<_>>();
+        let expected = ["List", "Bits", "Parts"].map(String::from);
+        assert_eq!(recursive, HashSet::from(expected));
+    }
+
+    #[test]
+    fn test_types_are_keyed_and_resolved_by_module() {
+        let file = syn::parse_file(
+            "
+            mod list {
+                pub struct Node { next: *mut Node, value: super::tree::Value }
             }
-            (t1, t2) => Err(Error::UnUnifiableTypes(t1.clone(), t2.clone())),
+            mod tree {
+                use crate::list::Node as ListNode;
+                pub type Value = i32;
+                pub struct Node { children: Vec<ListNode>, value: Value }
+            }
+            mod reexport {
+                pub use super::tree::*;
+            }
+            struct Root { leaf: reexport::Node, list: list::Node }
+            ",
+        )
+        .unwrap();
+        let mut collector = CTypeContextCollector::default();
+        syn::visit::Visit::visit_file(&mut collector, &file);
+        let mut ctxt = collector.to_type_context();
+        normalize_type_context(&mut ctxt);
+
+        let path = |src: &str| RustPath::from(&syn::parse_str::<syn::Path>(src).unwrap());
+        let ty = |src: &str| RustType::from(syn::parse_str::<syn::Type>(src).unwrap());
+        assert_eq!(ctxt.1.len(), 3);
+        let list_node = &ctxt.1[&path("list::Node")];
+        assert_eq!(
+            list_node.fields().types(),
+            vec![&ty("*mut list::Node"), &RustType::I32]
+        );
+        let tree_node = &ctxt.1[&path("tree::Node")];
+        assert_eq!(tree_node.fields().types()[0], &ty("Vec<list::Node>"));
+        let root = &ctxt.1[&path("Root")];
+        assert_eq!(
+            root.fields().types(),
+            vec![&ty("tree::Node"), &ty("list::Node")]
+        );
+    }
+
+    #[test]
+    fn test_size_t_unifies_with_usize() {
+        let ty = RustType::unify_values(&RustType::SizeT, &RustType::Usize).unwrap();
+        assert_eq!(ty, RustType::Usize);
+        assert!(RustType::unify_values(&RustType::I64, &RustType::U64).is_err());
+    }
+
+    #[test]
+    fn test_type_map_collects_functions_methods_and_modules() {
+        use crate::filesystem::FileSystem;
+
+        let fs = crate::filesystem::SymbolicFileSystem::default();
+        let files = [
+            (
+                "src/lib.rs",
+                "mod shapes; pub fn area_sum(xs: &[f64]) -> f64 { fn nested() {} 0.0 }",
+            ),
+            (
+                "src/shapes.rs",
+                "pub mod circle;
+                pub trait Shape { fn area(&self) -> f64; fn scaled<T: Into<f64>>(&self, by: T) -> Self; }",
+            ),
+            (
+                "src/shapes/circle.rs",
+                "use super::Shape;
+                pub struct Circle { r: f64 }
+                impl Circle { pub fn new(r: f64) -> Self { Circle { r } } }
+                impl Shape for Circle {
+                    fn area(&self) -> f64 { self.r }
+                    fn scaled<T: Into<f64>>(&self, by: T) -> Self { Circle::new(self.r * by.into()) }
+                }
+                impl std::fmt::Display for Circle {
+                    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result { Ok(()) }
+                }
+                pub struct Grid<T> { cells: Vec<T> }
+                impl<T: Copy> Grid<T> {
+                    pub fn filled(cells: Vec<T>) -> Self { Grid { cells } }
+                    pub fn get(&self, i: usize) -> T { self.cells[i] }
+                    pub fn map<U>(&self, f: fn(T) -> U) -> Grid<U> where T: Default { todo!() }
+                }",
+            ),
+        ];
+        for (file, src) in files {
+            fs.write(file, src).unwrap();
         }
+        let mut collector = TypeMapCollector::default();
+        collector
+            .visit_crate(&fs, std::path::Path::new("src/lib.rs"))
+            .unwrap();
+        assert!(collector.errors().is_empty(), "{:?}", collector.errors());
+
+        let types = collector.to_type_map();
+        let mut names = types
+            .iter()
+            .map(|(loc, sig)| format!("{} {}", loc.full_fn_name(), sig))
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "crate::area_sum fn area_sum<>(xs: ref_immutable_slice(f64)) -> f64",
+                "crate::shapes::Shape::area fn area<>(&self) -> f64",
+                "crate::shapes::Shape::scaled fn scaled<T0: Into<f64>>(&self,by: T0) -> Self",
+                "crate::shapes::circle::<Circle as crate::shapes::Shape>::area fn area<>(&self) -> f64",
+                "crate::shapes::circle::<Circle as crate::shapes::Shape>::scaled fn scaled<T0: Into<f64>>(&self,by: T0) -> Circle",
+                "crate::shapes::circle::<Circle as std::fmt::Display>::fmt fn fmt<>(&self,f: ref_mutable_adt(std::fmt::Formatter, [])) -> adt(std::fmt::Result, [])",
+                "crate::shapes::circle::Circle::new fn new<>(r: f64) -> Circle",
+                "crate::shapes::circle::Grid::filled fn filled<T0: Copy>(cells: vec(T0)) -> adt(Grid, [T0])",
+                "crate::shapes::circle::Grid::get fn get<T0: Copy>(&self,i: usize) -> T0",
+                "crate::shapes::circle::Grid::map fn map<T0: Copy + Default, T1: >(&self,f: fn_ptr(safe, rust, [T0], fixed, T1)) -> adt(Grid, [T1])",
+            ]
+        );
+        let (loc, _) = types
+            .iter()
+            .find(|(loc, _)| loc.fn_name() == "new")
+            .unwrap();
+        assert!(loc.path().ends_with("shapes/circle.rs"));
     }
 }
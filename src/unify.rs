@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+
+use crate::typ::{Error, RustType, RustTypeConstraint, TVar};
+
+/// Mapping of type variables to the types they have been bound to
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Substitution(HashMap<TVar, RustType>);
+
+impl Substitution {
+    pub fn new() -> Self {
+        Substitution(HashMap::new())
+    }
+
+    /// Returns the type directly bound to `var`, if any
+    pub fn get(&self, var: &TVar) -> Option<&RustType> {
+        self.0.get(var)
+    }
+
+    /// Binds `var` to `ty`, overwriting any previous binding
+    pub fn bind(&mut self, var: TVar, ty: RustType) {
+        self.0.insert(var, ty);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&TVar, &RustType)> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Applies the substitution to a type, following chains of bindings until no bound type
+    /// variable remains
+    pub fn apply(&self, ty: &RustType) -> RustType {
+        match ty {
+            RustType::TVar(var) => match self.0.get(var) {
+                Some(bound) => self.apply(bound),
+                None => ty.clone(),
+            },
+            ty => {
+                let mut ty = ty.clone();
+                for child in ty.children_mut() {
+                    *child = self.apply(child);
+                }
+                ty
+            }
+        }
+    }
+}
+
+impl FromIterator<(TVar, RustType)> for Substitution {
+    fn from_iter<I: IntoIterator<Item = (TVar, RustType)>>(iter: I) -> Self {
+        Substitution(iter.into_iter().collect())
+    }
+}
+
+/// Returns true if `var` occurs anywhere inside `ty`
+fn occurs(var: &TVar, ty: &RustType) -> bool {
+    match ty {
+        RustType::TVar(other) => var == other,
+        ty => ty.children().into_iter().any(|child| occurs(var, child)),
+    }
+}
+
+/// Returns true if two constraints only differ in the types they mention
+fn same_constraint_shape(c1: &RustTypeConstraint, c2: &RustTypeConstraint) -> bool {
+    match (c1, c2) {
+        (RustTypeConstraint::Index(..), RustTypeConstraint::Index(..))
+        | (RustTypeConstraint::IndexMut(..), RustTypeConstraint::IndexMut(..)) => true,
+        (RustTypeConstraint::Trait(p1, args1, b1), RustTypeConstraint::Trait(p2, args2, b2)) => {
+            p1 == p2
+                && args1.len() == args2.len()
+                && b1.len() == b2.len()
+                && b1.iter().zip(b2.iter()).all(|((n1, _), (n2, _))| n1 == n2)
+        }
+        (RustTypeConstraint::Fn(p1, args1, _), RustTypeConstraint::Fn(p2, args2, _)) => {
+            p1 == p2 && args1.len() == args2.len()
+        }
+        (RustTypeConstraint::Outlives(l1), RustTypeConstraint::Outlives(l2)) => l1 == l2,
+        (RustTypeConstraint::Maybe(p1), RustTypeConstraint::Maybe(p2)) => p1 == p2,
+        _ => false,
+    }
+}
+
+/// Returns true if the outermost constructors of two types match, so that unification can
+/// proceed on their children
+fn same_shape(t1: &RustType, t2: &RustType) -> bool {
+    match (t1, t2) {
+        (RustType::Option(_), RustType::Option(_))
+        | (RustType::Vec(_), RustType::Vec(_))
+        | (RustType::Slice(_), RustType::Slice(_)) => true,
+        (RustType::Array(_, n1), RustType::Array(_, n2)) => n1 == n2,
+        (RustType::Pointer(m1, _), RustType::Pointer(m2, _)) => m1 == m2,
+        (RustType::Reference(l1, m1, _), RustType::Reference(l2, m2, _)) => {
+            // an elided lifetime is compatible with any other lifetime
+            m1 == m2 && (l1.is_none() || l2.is_none() || l1 == l2)
+        }
+        (RustType::Tuple(e1), RustType::Tuple(e2)) => e1.len() == e2.len(),
+        (RustType::Adt(p1, l1, a1), RustType::Adt(p2, l2, a2)) => {
+            // elided lifetime arguments are compatible with any others
+            p1 == p2 && (l1.is_empty() || l2.is_empty() || l1 == l2) && a1.len() == a2.len()
+        }
+        (
+            RustType::FnPtr {
+                unsafety: u1,
+                abi: abi1,
+                args: a1,
+                variadic: v1,
+                ..
+            },
+            RustType::FnPtr {
+                unsafety: u2,
+                abi: abi2,
+                args: a2,
+                variadic: v2,
+                ..
+            },
+        ) => u1 == u2 && abi1 == abi2 && v1 == v2 && a1.len() == a2.len(),
+        (RustType::TraitObject(b1), RustType::TraitObject(b2))
+        | (RustType::Opaque(b1), RustType::Opaque(b2)) => {
+            b1.len() == b2.len()
+                && b1
+                    .iter()
+                    .zip(b2.iter())
+                    .all(|(c1, c2)| same_constraint_shape(c1, c2))
+        }
+        _ => false,
+    }
+}
+
+/// Structural unifier over `RustType`s, accumulating a substitution of type variables
+#[derive(Clone, Debug, Default)]
+pub struct Unifier {
+    subst: Substitution,
+}
+
+impl Unifier {
+    pub fn new() -> Self {
+        Unifier::default()
+    }
+
+    /// Creates a unifier that starts from an existing set of bindings
+    pub fn with_substitution(subst: Substitution) -> Self {
+        Unifier { subst }
+    }
+
+    pub fn substitution(&self) -> &Substitution {
+        &self.subst
+    }
+
+    pub fn into_substitution(self) -> Substitution {
+        self.subst
+    }
+
+    /// Unifies two types, extending the substitution, and returns the unified type with the
+    /// substitution applied
+    pub fn unify(&mut self, t1: &RustType, t2: &RustType) -> Result<RustType, Error> {
+        let mut path = vec![];
+        let saved = self.subst.clone();
+        match self.unify_at(&mut path, t1, t2) {
+            Ok(ty) => Ok(self.subst.apply(&ty)),
+            Err(err) => {
+                // leave the substitution untouched on failure
+                self.subst = saved;
+                Err(err)
+            }
+        }
+    }
+
+    fn bind(&mut self, path: &[usize], var: TVar, ty: &RustType) -> Result<RustType, Error> {
+        let ty = self.subst.apply(ty);
+        if ty == RustType::TVar(var) {
+            return Ok(ty);
+        }
+        if occurs(&var, &ty) {
+            return Err(Error::Occurs {
+                path: path.to_vec(),
+                var,
+                ty: Box::new(ty),
+            });
+        }
+        self.subst.bind(var, ty.clone());
+        Ok(ty)
+    }
+
+    fn unify_at(
+        &mut self,
+        path: &mut Vec<usize>,
+        t1: &RustType,
+        t2: &RustType,
+    ) -> Result<RustType, Error> {
+        match (t1, t2) {
+            (RustType::TVar(v), ty) | (ty, RustType::TVar(v)) if self.subst.get(v).is_some() => {
+                let bound = self.subst.get(v).unwrap().clone();
+                self.unify_at(path, &bound, ty)
+            }
+            (RustType::TVar(v), ty) | (ty, RustType::TVar(v)) => self.bind(path, *v, ty),
+            (t1, t2) if t1 == t2 => Ok(t1.clone()),
+            // libc::size_t is a type alias of usize
+            (RustType::SizeT, RustType::Usize) | (RustType::Usize, RustType::SizeT) => {
+                Ok(RustType::Usize)
+            }
+            (t1, t2) if same_shape(t1, t2) => {
+                let mut unified = match (t1, t2) {
+                    // keep whichever lifetime was written explicitly
+                    (RustType::Reference(None, _, _), t2 @ RustType::Reference(..)) => t2.clone(),
+                    (t1, _) => t1.clone(),
+                };
+                let children = t1.children().into_iter().zip(t2.children());
+                let children = children
+                    .enumerate()
+                    .map(|(i, (c1, c2))| {
+                        path.push(i);
+                        let res = self.unify_at(path, c1, c2);
+                        path.pop();
+                        res
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                for (slot, child) in unified.children_mut().into_iter().zip(children) {
+                    *slot = child;
+                }
+                Ok(unified)
+            }
+            (t1, t2) => Err(Error::Mismatch {
+                path: path.clone(),
+                left: Box::new(self.subst.apply(t1)),
+                right: Box::new(self.subst.apply(t2)),
+            }),
+        }
+    }
+}
+
+/// Unifies two types from scratch, returning the unified type and the bindings required
+pub fn unify(t1: &RustType, t2: &RustType) -> Result<(RustType, Substitution), Error> {
+    let mut unifier = Unifier::new();
+    let ty = unifier.unify(t1, t2)?;
+    Ok((ty, unifier.into_substitution()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typ::RustMutability;
+    use crate::CHRusty_parse;
+
+    fn ty(src: &str) -> RustType {
+        CHRusty_parse!(src as syn::Type).into()
+    }
+
+    #[test]
+    fn test_unify_binds_type_variables_structurally() {
+        let (unified, subst) = unify(
+            &RustType::Reference(
+                None,
+                RustMutability::Mutable,
+                Box::new(RustType::Vec(Box::new(RustType::TVar(TVar(0))))),
+            ),
+            &ty("&mut Vec<Option<u8>>"),
+        )
+        .unwrap();
+        assert_eq!(unified, ty("&mut Vec<Option<u8>>"));
+        assert_eq!(subst.get(&TVar(0)), Some(&ty("Option<u8>")));
+    }
+
+    #[test]
+    fn test_unify_follows_existing_bindings() {
+        let mut unifier = Unifier::new();
+        let pair = |a, b| RustType::Tuple(vec![RustType::TVar(TVar(a)), RustType::TVar(TVar(b))]);
+        unifier.unify(&pair(0, 1), &pair(1, 2)).unwrap();
+        let unified = unifier
+            .unify(
+                &RustType::TVar(TVar(0)),
+                &RustType::Array(Box::new(RustType::I32), 4),
+            )
+            .unwrap();
+        assert_eq!(unified, ty("[i32; 4]"));
+        assert_eq!(
+            unifier.substitution().apply(&RustType::TVar(TVar(2))),
+            ty("[i32; 4]")
+        );
+    }
+
+    #[test]
+    fn test_unify_performs_occurs_check() {
+        let var = RustType::TVar(TVar(0));
+        let boxed = RustType::Option(Box::new(var.clone()));
+        match unify(
+            &RustType::Tuple(vec![RustType::I32, var.clone()]),
+            &RustType::Tuple(vec![RustType::I32, boxed.clone()]),
+        ) {
+            Err(Error::Occurs { path, var, .. }) => {
+                assert_eq!(path, vec![1]);
+                assert_eq!(var, TVar(0));
+            }
+            res => panic!("expected occurs check failure, got {:?}", res),
+        }
+        assert!(unify(&RustType::TVar(TVar(0)), &boxed).is_err());
+    }
+
+    #[test]
+    fn test_unify_reports_the_mismatching_sub_term() {
+        let fn1 = ty("unsafe extern \"C\" fn(*mut u8, Vec<i32>) -> i32");
+        let fn2 = ty("unsafe extern \"C\" fn(*mut u8, Vec<u32>) -> i32");
+        match unify(&fn1, &fn2) {
+            Err(Error::Mismatch { path, left, right }) => {
+                assert_eq!(path, vec![1, 0]);
+                assert_eq!(*left, RustType::I32);
+                assert_eq!(*right, RustType::U32);
+            }
+            res => panic!("expected a mismatch, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_failed_unification_leaves_substitution_untouched() {
+        let mut unifier = Unifier::new();
+        let lhs = RustType::Tuple(vec![RustType::TVar(TVar(0)), RustType::Bool]);
+        assert!(unifier.unify(&lhs, &ty("(u8, char)")).is_err());
+        assert!(unifier.substitution().is_empty());
+    }
+}
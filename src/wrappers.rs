@@ -1,6 +1,11 @@
 use quote::ToTokens;
 
-use crate::{pprint_ast, typ::RustType, CHRusty_build, CHRusty_parse};
+use crate::{
+    pprint_ast,
+    traits::TraitEnv,
+    typ::{RustMutability, RustPath, RustType, RustTypeConstraint},
+    CHRusty_build, CHRusty_parse,
+};
 
 /// Represents a wrapper struct that encodes the fact that a given pointer should implement indexed
 #[derive(Clone, Debug)]
@@ -31,7 +36,7 @@ fn extract_path_argument(argument: syn::PathArguments) -> Option<RustType> {
         syn::PathArguments::AngleBracketed(syn::AngleBracketedGenericArguments {
             args, ..
         }) if args.len() == 1 => match &args[0] {
-            syn::GenericArgument::Type(ty) => Some(ty.clone().into()),
+            syn::GenericArgument::Type(ty) => RustType::try_from(ty).ok(),
             _ => None,
         },
         _ => None,
@@ -110,6 +115,32 @@ impl IndexWrapper {
         &self.ty
     }
 
+    /// Mutability of the pointer being wrapped, if the base type is a raw pointer
+    pub fn base_mutability(&self) -> Option<&RustMutability> {
+        match &self.ty {
+            RustType::Pointer(mutability, _) => Some(mutability),
+            _ => None,
+        }
+    }
+
+    /// Type of the elements reached by indexing through every level of the wrapper, e.g `i32`
+    /// for a wrapper of depth 2 over `*mut *mut i32`. Each level indexes the pointer it is
+    /// given through `chrusty::IndexWrapperBase`, whose impls are part of `TraitEnv::builtin`
+    pub fn element_ty(&self, env: &TraitEnv) -> Option<RustType> {
+        let base = RustPath::from(&CHRusty_parse!("chrusty::IndexWrapperBase" as syn::Path));
+        let index = RustTypeConstraint::Trait(
+            RustPath::from(&CHRusty_parse!("Index" as syn::Path)),
+            vec![RustType::Usize],
+            vec![],
+        );
+        let mut ty = self.ty.clone();
+        for _ in 0..self.indirection {
+            let wrapped = RustType::Adt(base.clone(), vec![], vec![ty]);
+            ty = env.associated_type(&wrapped, &index, "Output")?;
+        }
+        Some(ty)
+    }
+
     /// Test whether an expression is indeed an index wrapper
     pub fn is_index_wrapper(expr: &syn::Expr) -> bool {
         match &expr {
@@ -312,6 +343,34 @@ mod tests {
         assert_eq!(&pprint_ast!(base_ty), "* mut * mut * mut i32")
     }
 
+    #[test]
+    fn test_index_wrapper_conversion_preserves_const_pointers() {
+        let base_expr = CHRusty_parse!("x.as_ptr()" as syn::Expr);
+        let base_ty = CHRusty_parse!("*const *mut i32" as syn::Type).into();
+
+        let wrapper: IndexWrapper = IndexWrapper::new(2, base_expr, base_ty);
+
+        let wrapper_expr: syn::Expr = wrapper.into();
+
+        let wrapper: IndexWrapper = wrapper_expr.into();
+
+        assert_eq!(wrapper.base_mutability(), Some(&RustMutability::Immutable));
+        let base_ty: syn::Type = wrapper.ty.into();
+        assert_eq!(&pprint_ast!(base_ty), "* const * mut i32")
+    }
+
+    #[test]
+    fn test_index_wrapper_element_type_follows_each_level() {
+        let env = TraitEnv::builtin();
+        let expr = CHRusty_parse!("x.as_mut_ptr()" as syn::Expr);
+        let base_ty: RustType = CHRusty_parse!("*mut *const u8" as syn::Type).into();
+
+        let wrapper = IndexWrapper::new(2, expr.clone(), base_ty.clone());
+        assert_eq!(wrapper.element_ty(&env), Some(RustType::U8));
+        let wrapper = IndexWrapper::new(3, expr, base_ty);
+        assert_eq!(wrapper.element_ty(&env), None);
+    }
+
     #[test]
     fn test_index_wrapper_has_correct_internal_structure() {
         let base_expr = CHRusty_parse!("x.as_mut_ptr()" as syn::Expr);